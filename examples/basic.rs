@@ -1,7 +1,3 @@
-use std::sync::atomic::{AtomicBool, Ordering};
-
-static RUNNING: AtomicBool = AtomicBool::new(true);
-
 fn main() -> Result<(), wintun_bindings::BoxError> {
     dotenvy::dotenv().ok();
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("trace")).init();
@@ -26,17 +22,16 @@ fn main() -> Result<(), wintun_bindings::BoxError> {
 
     let reader_session = session.clone();
     let reader = std::thread::spawn(move || {
-        while RUNNING.load(Ordering::Relaxed) {
-            let packet = reader_session.receive_blocking();
-            if let Err(err) = packet {
-                log::info!("Error reading packet: {:?}", err);
-                break;
-            }
+        // The iterator ends once the session is shut down
+        for packet in reader_session.iter_blocking() {
             let packet = packet?;
-            let bytes = packet.bytes();
-            let len = bytes.len();
-            let data = &bytes[0..(20.min(bytes.len()))];
-            println!("Read packet size {} bytes. Header data: {:?}", len, data);
+            let len = packet.bytes().len();
+            match (packet.ip_version(), packet.addresses()) {
+                (Some(version), Some((src, dst))) => {
+                    println!("Read IPv{} packet size {} bytes from {} to {}", version, len, src, dst)
+                }
+                _ => println!("Read malformed packet size {} bytes", len),
+            }
         }
         Ok::<(), wintun_bindings::Error>(())
     });
@@ -46,7 +41,6 @@ fn main() -> Result<(), wintun_bindings::BoxError> {
     let _ = std::io::stdin().read_line(&mut line);
     println!("Shutting down session");
 
-    RUNNING.store(false, Ordering::Relaxed);
     session.shutdown()?;
     let _ = reader
         .join()
@@ -122,8 +122,7 @@ fn main() -> Result<(), BoxError> {
     );
 
     let session = adapter.start_session(MAX_RING_CAPACITY)?;
-    let reader_session = session.clone();
-    let writer_session = session.clone();
+    let (reader_session, writer_session) = session.clone().split();
 
     let (tx, rx) = channel::<NaiveUdpPacket>();
 
@@ -183,8 +183,7 @@ fn main() -> Result<(), BoxError> {
     let mut writer = pcap_file::pcap::PcapWriter::with_header(file, header)?;
     let main_session = adapter.start_session(wintun_bindings::MAX_RING_CAPACITY)?;
 
-    let reader_session = main_session.clone();
-    let writer_session = main_session.clone();
+    let (reader_session, writer_session) = main_session.clone().split();
 
     let reader = std::thread::spawn(move || {
         let mut packet_count = 0;
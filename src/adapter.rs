@@ -6,43 +6,121 @@
 use crate::{
     error::{Error, OutOfRangeData},
     handle::{SafeEvent, UnsafeHandle},
+    route::{self, RouteEntry},
     session::Session,
     util::{self},
     wintun_raw, Wintun,
 };
 use std::{
     ffi::OsStr,
-    net::{IpAddr, Ipv4Addr},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
     os::windows::prelude::OsStrExt,
     ptr,
+    sync::atomic::{AtomicU32, Ordering},
     sync::Arc,
     sync::OnceLock,
 };
 use windows_sys::{
     core::GUID,
-    Win32::NetworkManagement::{IpHelper::ConvertLengthToIpv4Mask, Ndis::NET_LUID_LH},
+    Win32::{
+        Foundation::ERROR_OBJECT_ALREADY_EXISTS,
+        NetworkManagement::{
+            IpHelper::ConvertLengthToIpv4Mask,
+            Ndis::{IfOperStatusUp, NET_LUID_LH},
+        },
+        Networking::WinSock::{RouterDiscoveryDisabled, RouterDiscoveryEnabled},
+    },
 };
 
+/// The namespace used by [`Adapter::name_derived_guid`], `{1c995925-17ce-4a69-b60a-2a5dba6486e5}`
+pub const ADAPTER_GUID_NAMESPACE: u128 = 0x1c995925_17ce_4a69_b60a_2a5dba6486e5;
+
+/// Number of attempts [`Adapter::create`] makes to resolve the LUID of the new interface
+static LUID_RESOLVE_ATTEMPTS: AtomicU32 = AtomicU32::new(10);
+
+/// Netmask [`Adapter::set_address`] uses when the adapter has no IPv4 address yet, `255.255.255.0`
+static DEFAULT_IPV4_NETMASK: AtomicU32 = AtomicU32::new(0xffff_ff00);
+
+/// The network category of a connection, which selects the Windows Firewall profile applied to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NetworkCategory {
+    Public,
+    Private,
+    /// Assigned by Windows to networks with a domain controller, it cannot be set manually
+    DomainAuthenticated,
+}
+
+impl NetworkCategory {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            NetworkCategory::Public => "Public",
+            NetworkCategory::Private => "Private",
+            NetworkCategory::DomainAuthenticated => "DomainAuthenticated",
+        }
+    }
+}
+
+impl std::str::FromStr for NetworkCategory {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "Public" => Ok(NetworkCategory::Public),
+            "Private" => Ok(NetworkCategory::Private),
+            "DomainAuthenticated" => Ok(NetworkCategory::DomainAuthenticated),
+            other => Err(format!("Unknown network category \"{}\"", other).into()),
+        }
+    }
+}
+
+/// Traffic counters of an adapter, returned by [`Adapter::get_statistics`]
+///
+/// The counters are cumulative since the interface was created, as reported by Windows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Statistics {
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    /// Unicast and non-unicast packets received
+    pub rx_packets: u64,
+    /// Unicast and non-unicast packets sent
+    pub tx_packets: u64,
+    pub rx_errors: u64,
+    pub tx_errors: u64,
+    /// Received packets discarded without error, e.g. because the receive ring was full
+    pub rx_discards: u64,
+    pub tx_discards: u64,
+}
+
+/// An IP address family, selecting e.g. which one [`Adapter::set_mtu_for`] applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AddressFamily {
+    Ipv4,
+    Ipv6,
+}
+
 /// Wrapper around a <https://git.zx2c4.com/wintun/about/#wintun_adapter_handle>
 pub struct Adapter {
     adapter: UnsafeHandle<wintun_raw::WINTUN_ADAPTER_HANDLE>,
     pub(crate) wintun: Wintun,
     guid: u128,
-    index: u32,
+    /// Interface index, which can change e.g. after a resume, see [`Adapter::refresh`]
+    index: AtomicU32,
     luid: NET_LUID_LH,
+    /// Cached result of [`Adapter::get_device_name`]
+    device_name: OnceLock<String>,
 }
 
 impl Adapter {
     /// Returns the `Friendly Name` of this adapter,
     /// which is the human readable name shown in Windows
     pub fn get_name(&self) -> Result<String, Error> {
-        Ok(crate::ffi::luid_to_alias(&self.luid)?)
+        crate::ffi::luid_to_alias(&self.luid)
     }
 
     /// Sets the `Friendly Name` of this adapter,
     /// which is the human readable name shown in Windows
     ///
-    /// Note: This is different from `Adapter Name`, which is a GUID.
+    /// Note: This is different from `Adapter Name`, which is a GUID, see [`Adapter::get_device_name`].
     pub fn set_name(&self, name: &str) -> Result<(), Error> {
         // use command `netsh interface set interface name="oldname" newname="mynewname"`
 
@@ -58,10 +136,85 @@ impl Adapter {
         Ok(())
     }
 
+    /// Returns the `Interface Description` of this adapter, which is shown as `Description` by
+    /// `ipconfig /all` and can be used by netsh commands and firewall rules to select the interface
+    pub fn get_description(&self) -> Result<String, Error> {
+        let row = util::get_if_row2(&self.luid)?;
+        Ok(util::decode_utf16(&row.Description))
+    }
+
+    /// Sets the `Interface Description` of this adapter by writing `DriverDesc` of its network
+    /// class registry key.
+    ///
+    /// Requires administrator privileges. The new description is reported by
+    /// [`Adapter::get_description`] once the adapter has been restarted, e.g. disabled and enabled
+    /// again or recreated with the same GUID.
+    #[cfg(feature = "winreg")]
+    pub fn set_description(&self, description: &str) -> Result<(), Error> {
+        use winreg::{enums::HKEY_LOCAL_MACHINE, enums::KEY_ALL_ACCESS, RegKey};
+        const NET_CLASS_KEY: &str = "SYSTEM\\CurrentControlSet\\Control\\Class\\{4d36e972-e325-11ce-bfc1-08002be10318}";
+
+        let guid = self.get_device_name()?;
+        let hklm = RegKey::predef(HKEY_LOCAL_MACHINE);
+        let class_key = hklm.open_subkey(NET_CLASS_KEY)?;
+        for sub_key_name in class_key.enum_keys().filter_map(Result::ok) {
+            let Ok(sub_key) = class_key.open_subkey_with_flags(&sub_key_name, KEY_ALL_ACCESS) else {
+                continue;
+            };
+            match sub_key.get_value::<String, _>("NetCfgInstanceId") {
+                Ok(id) if id.eq_ignore_ascii_case(&guid) => {
+                    let key_s = format!("key={}\\{}", NET_CLASS_KEY, sub_key_name);
+                    let value_s = format!("DriverDesc={}", description);
+                    util::notify_command_observer("RegSetValueEx", &[&key_s, &value_s]);
+                    sub_key.set_value("DriverDesc", &description)?;
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+        Err(format!("Unable to find the registry key of adapter {}", guid).into())
+    }
+
+    /// Returns the `Adapter Name` of this adapter, the GUID string such as
+    /// `{6BA8E5B1-2C3D-4E5F-8A9B-0C1D2E3F4A5B}` that identifies the device in the registry and in
+    /// `GetAdaptersAddresses`. Unlike the friendly name returned by [`Adapter::get_name`] it never
+    /// changes, so it is cached.
+    pub fn get_device_name(&self) -> Result<String, Error> {
+        if let Some(name) = self.device_name.get() {
+            return Ok(name.clone());
+        }
+        let name = util::guid_to_win_style_string(&GUID::from_u128(self.guid))?;
+        Ok(self.device_name.get_or_init(|| name).clone())
+    }
+
+    /// Returns the TCP/IP device path of this adapter, such as
+    /// `\DEVICE\TCPIP_{6BA8E5B1-2C3D-4E5F-8A9B-0C1D2E3F4A5B}`, as used by `GetInterfaceInfo`, WFP
+    /// and some registry settings.
+    ///
+    /// `GetInterfaceInfo` only lists interfaces with IPv4 enabled, for others the path is built from
+    /// [`Adapter::get_device_name`].
+    pub fn get_device_instance_path(&self) -> Result<String, Error> {
+        let guid = util::get_interface_info()?
+            .into_iter()
+            .find(|(index, _)| *index == self.index())
+            .map(|(_, guid)| format!("{{{}}}", guid));
+        let guid = match guid {
+            Some(guid) => guid,
+            None => self.get_device_name()?,
+        };
+        Ok(format!("\\DEVICE\\TCPIP_{}", guid))
+    }
+
     pub fn get_guid(&self) -> u128 {
         self.guid
     }
 
+    /// Returns the GUID of this adapter as a [`uuid::Uuid`]
+    #[cfg(feature = "uuid")]
+    pub fn get_uuid(&self) -> uuid::Uuid {
+        uuid::Uuid::from_u128(self.guid)
+    }
+
     /// Creates a new wintun adapter inside the name `name` with tunnel type `tunnel_type`
     ///
     /// Optionally a GUID can be specified that will become the GUID of this adapter once created.
@@ -81,13 +234,14 @@ impl Adapter {
         crate::log::set_default_logger_if_unset(wintun);
 
         let guid_s: GUID = GUID::from_u128(guid);
+        let _context = crate::log::log_context(name);
         let result = unsafe { wintun.WintunCreateAdapter(name_utf16.as_ptr(), tunnel_type_utf16.as_ptr(), &guid_s) };
 
         if result.is_null() {
             return crate::log::extract_wintun_log_error("WintunCreateAdapter failed")?;
         }
         let mut call = || -> Result<Arc<Adapter>, Error> {
-            let luid = crate::ffi::alias_to_luid(name)?;
+            let luid = Self::resolve_luid_with_retry(name)?;
             let index = crate::ffi::luid_to_index(&luid)?;
             let real_guid = util::win_guid_to_u128(&crate::ffi::luid_to_guid(&luid)?);
             if guid != real_guid {
@@ -97,12 +251,15 @@ impl Adapter {
                 log::warn!("Windows {major}.{minor}.{build} internal bug cause the GUID mismatch: Expected {guid_s}, got {real_guid_s}");
                 guid = real_guid;
             }
+            #[cfg(debug_assertions)]
+            crate::LIVE_ADAPTERS.fetch_add(1, Ordering::SeqCst);
             Ok(Arc::new(Adapter {
                 adapter: UnsafeHandle(result),
                 wintun: wintun.clone(),
                 guid,
-                index,
+                index: AtomicU32::new(index),
                 luid,
+                device_name: OnceLock::new(),
             }))
         };
         match call() {
@@ -114,12 +271,169 @@ impl Adapter {
         }
     }
 
+    /// Returns the GUID deterministically derived from the adapter name `name`: the version 5 UUID
+    /// (SHA-1, RFC 4122) of the UTF-8 bytes of `name` in the namespace [`ADAPTER_GUID_NAMESPACE`]
+    pub fn name_derived_guid(name: &str) -> u128 {
+        util::uuid_v5(ADAPTER_GUID_NAMESPACE, name.as_bytes())
+    }
+
+    /// Creates a new wintun adapter like [`Adapter::create`], using the GUID derived from `name` by
+    /// [`Adapter::name_derived_guid`], so the adapter gets the same GUID every time it is created.
+    pub fn create_with_name_derived_guid(
+        wintun: &Wintun,
+        name: &str,
+        tunnel_type: &str,
+    ) -> Result<Arc<Adapter>, Error> {
+        Self::create(wintun, name, tunnel_type, Some(Self::name_derived_guid(name)))
+    }
+
+    /// Creates a new wintun adapter like [`Adapter::create_strict`], with the GUID given as a
+    /// [`uuid::Uuid`].
+    ///
+    /// The GUIDs of this crate are `u128` in the order of their textual form, like `Uuid`, so
+    /// the mixed-endian byte layout of the Windows `GUID` struct doesn't get in the way.
+    #[cfg(feature = "uuid")]
+    pub fn create_with_uuid(
+        wintun: &Wintun,
+        name: &str,
+        tunnel_type: &str,
+        uuid: uuid::Uuid,
+    ) -> Result<Arc<Adapter>, Error> {
+        Self::create_strict(wintun, name, tunnel_type, uuid.as_u128())
+    }
+
+    /// Creates a new wintun adapter like [`Adapter::create`], requiring the GUID `guid` instead of
+    /// falling back to a random one.
+    ///
+    /// Fails, removing the adapter again, if Windows assigned a different GUID than `guid`, which
+    /// [`Adapter::create`] only logs as a warning.
+    pub fn create_strict(wintun: &Wintun, name: &str, tunnel_type: &str, guid: u128) -> Result<Arc<Adapter>, Error> {
+        let adapter = Self::create(wintun, name, tunnel_type, Some(guid))?;
+        if adapter.get_guid() != guid {
+            let expected = util::guid_to_win_style_string(&GUID::from_u128(guid))?;
+            let actual = adapter.get_device_name()?;
+            return Err(format!("Adapter was created with GUID {actual} instead of {expected}").into());
+        }
+        Ok(adapter)
+    }
+
+    /// Creates a new wintun adapter like [`Adapter::create`] with the name `name`, then immediately
+    /// applies `friendly_name` as its `Friendly Name` before returning it.
+    ///
+    /// If the friendly name cannot be applied, the newly created adapter is removed again.
+    pub fn create_with_friendly_name(
+        wintun: &Wintun,
+        name: &str,
+        friendly_name: &str,
+        tunnel_type: &str,
+        guid: Option<u128>,
+    ) -> Result<Arc<Adapter>, Error> {
+        let adapter = Self::create(wintun, name, tunnel_type, guid)?;
+        if name != friendly_name {
+            adapter.set_name(friendly_name)?;
+        }
+        Ok(adapter)
+    }
+
+    /// Returns an [`AdapterBuilder`] creating the adapter `name`, configured with its setters
+    pub fn builder(name: &str) -> AdapterBuilder {
+        AdapterBuilder {
+            name: name.to_string(),
+            tunnel_type: "WinTun".to_string(),
+            guid: None,
+            ring_capacity: crate::MAX_RING_CAPACITY,
+            mtu: None,
+            address: None,
+            netmask: None,
+            gateway: None,
+            dns: Vec::new(),
+        }
+    }
+
+    /// Sets how many times [`Adapter::create`] tries to resolve the LUID of a newly created adapter,
+    /// which can fail transiently while the interface is still being registered. Defaults to 10.
+    pub fn set_luid_resolve_attempts(attempts: u32) {
+        LUID_RESOLVE_ATTEMPTS.store(attempts.max(1), Ordering::Relaxed);
+    }
+
+    fn resolve_luid_with_retry(name: &str) -> Result<NET_LUID_LH, Error> {
+        let attempts = LUID_RESOLVE_ATTEMPTS.load(Ordering::Relaxed).max(1);
+        let mut attempt = 1;
+        loop {
+            match crate::ffi::alias_to_luid(name) {
+                Ok(luid) => return Ok(luid),
+                Err(e) if attempt < attempts => {
+                    log::debug!("Failed to resolve LUID of \"{name}\" (attempt {attempt}/{attempts}): {e}");
+                    std::thread::sleep(std::time::Duration::from_millis(50));
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Returns whether a network interface named `name` exists, e.g. a wintun adapter left over
+    /// from a crash. Unlike [`Adapter::open`] this only looks up the interface alias and has no side effects.
+    pub fn exists(name: &str) -> bool {
+        crate::ffi::alias_to_luid(name).is_ok()
+    }
+
+    /// Returns the names of the wintun adapters present on the system, including the ones created
+    /// by other processes, e.g. left over after a crash. See [`Adapter::list_with_guids`].
+    pub fn list_wintun_adapters(wintun: &Wintun) -> Result<Vec<String>, Error> {
+        Ok(Self::list_with_guids(wintun)?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect())
+    }
+
+    /// Returns the names and GUIDs of the wintun adapters present on the system.
+    ///
+    /// Wintun adapters are virtual interfaces that wintun is able to open, so other virtual
+    /// interfaces such as Hyper-V switches are skipped.
+    pub fn list_with_guids(wintun: &Wintun) -> Result<Vec<(String, u128)>, Error> {
+        use windows_sys::Win32::NetworkManagement::IpHelper::IF_TYPE_PROP_VIRTUAL;
+        let mut candidates = vec![];
+        let mut error = None;
+        util::get_adapters_addresses(|adapter| {
+            if adapter.IfType != IF_TYPE_PROP_VIRTUAL {
+                return true;
+            }
+            match unsafe { util::win_pwstr_to_string(adapter.FriendlyName) } {
+                Ok(name) => candidates.push((name, adapter.Luid)),
+                Err(e) => {
+                    error = Some(e);
+                    return false;
+                }
+            }
+            true
+        })?;
+        if let Some(e) = error {
+            return Err(e);
+        }
+
+        let mut adapters = vec![];
+        for (name, luid) in candidates {
+            let name_utf16: Vec<u16> = OsStr::new(&name).encode_wide().chain(std::iter::once(0)).collect();
+            let handle = unsafe { wintun.WintunOpenAdapter(name_utf16.as_ptr()) };
+            if handle.is_null() {
+                continue;
+            }
+            // Only close the handle, dropping an `Adapter` would also clean up its registry info
+            unsafe { wintun.WintunCloseAdapter(handle) };
+            let guid = crate::ffi::luid_to_guid(&luid)?;
+            adapters.push((name, util::win_guid_to_u128(&guid)));
+        }
+        Ok(adapters)
+    }
+
     /// Attempts to open an existing wintun interface name `name`.
     pub fn open(wintun: &Wintun, name: &str) -> Result<Arc<Adapter>, Error> {
         let name_utf16: Vec<u16> = OsStr::new(name).encode_wide().chain(std::iter::once(0)).collect();
 
         crate::log::set_default_logger_if_unset(wintun);
 
+        let _context = crate::log::log_context(name);
         let result = unsafe { wintun.WintunOpenAdapter(name_utf16.as_ptr()) };
 
         if result.is_null() {
@@ -130,12 +444,15 @@ impl Adapter {
             let index = crate::ffi::luid_to_index(&luid)?;
             let guid = crate::ffi::luid_to_guid(&luid)?;
             let guid = util::win_guid_to_u128(&guid);
+            #[cfg(debug_assertions)]
+            crate::LIVE_ADAPTERS.fetch_add(1, Ordering::SeqCst);
             Ok(Arc::new(Adapter {
                 adapter: UnsafeHandle(result),
                 wintun: wintun.clone(),
                 guid,
-                index,
+                index: AtomicU32::new(index),
                 luid,
+                device_name: OnceLock::new(),
             }))
         };
         match call() {
@@ -147,6 +464,41 @@ impl Adapter {
         }
     }
 
+    /// Creates a new wintun adapter like [`Adapter::create`] and starts a session on it with the
+    /// ring buffer size `capacity`, see [`Adapter::start_session`].
+    ///
+    /// `capacity` is validated before the adapter is created. If the session fails to start, the
+    /// newly created adapter is closed, which removes it again.
+    #[doc(alias = "create_session")]
+    pub fn create_with_session(
+        wintun: &Wintun,
+        name: &str,
+        tunnel_type: &str,
+        guid: Option<u128>,
+        capacity: u32,
+    ) -> Result<(Arc<Adapter>, Arc<Session>), Error> {
+        Self::validate_capacity(capacity)?;
+        let adapter = Self::create(wintun, name, tunnel_type, guid)?;
+        let session = adapter.start_session(capacity)?;
+        Ok((adapter, session))
+    }
+
+    /// Opens an existing wintun adapter like [`Adapter::open`] and starts a session on it with the
+    /// ring buffer size `capacity`, see [`Adapter::start_session`].
+    ///
+    /// `capacity` is validated before the adapter is opened. If the session fails to start, the
+    /// adapter handle is closed again, the adapter itself is left in place.
+    pub fn open_with_session(
+        wintun: &Wintun,
+        name: &str,
+        capacity: u32,
+    ) -> Result<(Arc<Adapter>, Arc<Session>), Error> {
+        Self::validate_capacity(capacity)?;
+        let adapter = Self::open(wintun, name)?;
+        let session = adapter.start_session(capacity)?;
+        Ok((adapter, session))
+    }
+
     /// Delete an adapter, consuming it in the process
     pub fn delete(self) -> Result<(), Error> {
         //Dropping an adapter closes it
@@ -156,6 +508,26 @@ impl Adapter {
         Ok(())
     }
 
+    /// Deletes the existing wintun adapter named `name`, even if it was created by another process.
+    ///
+    /// Closing an adapter only removes it when it was created by this process, so the underlying
+    /// software device `SWD\Wintun\{GUID}` is removed with command `pnputil` (Windows 10 2004 or later).
+    pub fn delete_by_name(wintun: &Wintun, name: &str) -> Result<(), Error> {
+        let adapter = Self::open(wintun, name)?;
+        let guid = util::guid_to_win_style_string(&GUID::from_u128(adapter.get_guid()))?;
+        drop(adapter);
+
+        // command line: `pnputil /remove-device "SWD\Wintun\{GUID}"`
+        let instance_id = format!("SWD\\Wintun\\{}", guid);
+        util::run_command("pnputil", &["/remove-device", &instance_id])?;
+
+        #[cfg(feature = "winreg")]
+        if let Err(e) = delete_adapter_info_from_reg(name) {
+            log::warn!("Failed to delete registry info of adapter \"{}\": {}", name, e);
+        }
+        Ok(())
+    }
+
     fn validate_capacity(capacity: u32) -> Result<(), Error> {
         let range = crate::MIN_RING_CAPACITY..=crate::MAX_RING_CAPACITY;
         if !range.contains(&capacity) {
@@ -174,6 +546,7 @@ impl Adapter {
     pub fn start_session(self: &Arc<Self>, capacity: u32) -> Result<Arc<Session>, Error> {
         Self::validate_capacity(capacity)?;
 
+        let _context = self.get_name().map(|name| crate::log::log_context(&name));
         let result = unsafe { self.wintun.WintunStartSession(self.adapter.0, capacity) };
 
         if result.is_null() {
@@ -181,89 +554,445 @@ impl Adapter {
         }
         // Manual reset, because we use this event once and it must fire on all threads
         let shutdown_event = SafeEvent::new(true, false)?;
+        #[cfg(debug_assertions)]
+        crate::LIVE_SESSIONS.fetch_add(1, Ordering::SeqCst);
         Ok(Arc::new(Session {
             inner: UnsafeHandle(result),
             read_event: OnceLock::new(),
             shutdown_event: Arc::new(shutdown_event),
             adapter: self.clone(),
+            capacity,
+            pending_sends: Default::default(),
+            last_error_code: Default::default(),
+            spin_count: AtomicU32::new(crate::session::DEFAULT_SPIN_COUNT),
+            send_ring_full: Default::default(),
+            drain_on_shutdown: Default::default(),
+            #[cfg(debug_assertions)]
+            unsent_guard: Default::default(),
+            #[cfg(feature = "pcap")]
+            pcap_tee: Default::default(),
         }))
     }
 
+    /// Returns the raw WinTun adapter handle, for calling WinTun functions not wrapped by this crate.
+    ///
+    /// The handle is only valid for as long as this adapter is alive, it must not be used after the
+    /// adapter is dropped and must never be passed to `WintunCloseAdapter`, which happens on drop.
+    pub fn raw_handle(&self) -> wintun_raw::WINTUN_ADAPTER_HANDLE {
+        self.adapter.0
+    }
+
     /// Returns the Win32 LUID for this adapter
     pub fn get_luid(&self) -> NET_LUID_LH {
         self.luid
     }
 
-    /// Set `MTU` of this adapter
+    /// Returns the range of `MTU` values this adapter accepts, bounded by the link MTU the driver reports
+    pub fn get_mtu_range(&self) -> Result<std::ops::RangeInclusive<u32>, Error> {
+        Ok(util::MIN_IPV4_MTU..=self.max_supported_mtu()? as u32)
+    }
+
+    /// Returns the largest `MTU` this adapter supports, e.g. for jumbo frames.
+    ///
+    /// This is the link MTU the WinTun driver reports for the interface, which applies to both
+    /// address families.
+    pub fn max_supported_mtu(&self) -> Result<usize, Error> {
+        let row = util::get_if_row2(&self.luid)?;
+        Ok(row.Mtu as usize)
+    }
+
+    /// Set the IPv4 and IPv6 `MTU` of this adapter to the same value, see [`Adapter::set_mtu_for`]
+    /// to set them separately
+    ///
+    /// The value is checked against [`Adapter::get_mtu_range`] first, returning
+    /// [`Error::MtuOutOfRange`] without touching the adapter if it is not supported.
+    /// Values below the IPv6 minimum of 1280 are only applied to IPv4, returning
+    /// [`Error::MtuTooSmall`] instead of setting the IPv6 MTU.
     pub fn set_mtu(&self, mtu: usize) -> Result<(), Error> {
-        let name = self.get_name()?;
-        util::set_adapter_mtu(&name, mtu, false)?;
-        // FIXME: Here we set the IPv6 MTU as well for consistency, but for some users it may not be expected.
-        util::set_adapter_mtu(&name, mtu, true)?;
+        let value = self.check_mtu_range(mtu)?;
+        if value < util::MIN_IPV6_MTU {
+            self.set_mtu_of_families(value, &[false])?;
+            let minimum = util::MIN_IPV6_MTU;
+            return Err(Error::MtuTooSmall {
+                requested: value,
+                minimum,
+            });
+        }
+        self.set_mtu_of_families(value, &[false, true])
+    }
+
+    /// Set the `MTU` of one address family of this adapter, leaving the other one untouched
+    ///
+    /// Returns [`Error::MtuOutOfRange`] if the value is outside [`Adapter::get_mtu_range`], and
+    /// [`Error::MtuTooSmall`] for an IPv6 `MTU` below 1280, without touching the adapter.
+    pub fn set_mtu_for(&self, mtu: usize, family: AddressFamily) -> Result<(), Error> {
+        let value = self.check_mtu_range(mtu)?;
+        let is_ipv6 = family == AddressFamily::Ipv6;
+        if is_ipv6 && value < util::MIN_IPV6_MTU {
+            let minimum = util::MIN_IPV6_MTU;
+            return Err(Error::MtuTooSmall {
+                requested: value,
+                minimum,
+            });
+        }
+        self.set_mtu_of_families(value, &[is_ipv6])
+    }
+
+    /// Returns `mtu` as `u32`, or [`Error::MtuOutOfRange`] if it is outside [`Adapter::get_mtu_range`]
+    fn check_mtu_range(&self, mtu: usize) -> Result<u32, Error> {
+        let range = self.get_mtu_range()?;
+        let value = u32::try_from(mtu).unwrap_or(u32::MAX);
+        if !range.contains(&value) {
+            return Err(Error::MtuOutOfRange(OutOfRangeData { range, value }));
+        }
+        Ok(value)
+    }
+
+    /// Sets the MTU of the address families `is_ipv6` with a single mechanism, skipping the
+    /// families that already have the value `mtu`
+    fn set_mtu_of_families(&self, mtu: u32, is_ipv6: &[bool]) -> Result<(), Error> {
+        let changed = is_ipv6
+            .iter()
+            .copied()
+            .filter(|is_ipv6| match util::get_mtu_by_index(self.index(), *is_ipv6) {
+                Ok(current) => current != mtu,
+                Err(e) => {
+                    log::debug!("Failed to read current MTU, setting it anyway: {}", e);
+                    true
+                }
+            })
+            .collect::<Vec<_>>();
+        if changed.is_empty() {
+            return Ok(());
+        }
+        util::set_adapter_mtu_by_luid(&self.luid, &self.get_name()?, mtu as usize, &changed)?;
         Ok(())
     }
 
-    /// Returns `MTU` of this adapter
+    /// Returns the traffic counters of this adapter, read with `GetIfEntry2`
+    pub fn get_statistics(&self) -> Result<Statistics, Error> {
+        let row = util::get_if_row2(&self.luid)?;
+        Ok(Statistics {
+            rx_bytes: row.InOctets,
+            tx_bytes: row.OutOctets,
+            rx_packets: row.InUcastPkts + row.InNUcastPkts,
+            tx_packets: row.OutUcastPkts + row.OutNUcastPkts,
+            rx_errors: row.InErrors,
+            tx_errors: row.OutErrors,
+            rx_discards: row.InDiscards,
+            tx_discards: row.OutDiscards,
+        })
+    }
+
+    /// Returns the IPv4 `MTU` of this adapter, see [`Adapter::get_mtu_v6`] for IPv6
     pub fn get_mtu(&self) -> Result<usize, Error> {
-        // FIXME: Here we get the IPv4 MTU only, but for some users it may not be expected.
-        Ok(util::get_mtu_by_index(self.index, false)? as _)
+        self.get_mtu_of_family(false)
+    }
+
+    /// Returns the IPv6 `MTU` of this adapter, which may differ from the IPv4 one returned by
+    /// [`Adapter::get_mtu`]
+    pub fn get_mtu_v6(&self) -> Result<usize, Error> {
+        self.get_mtu_of_family(true)
+    }
+
+    /// Reads the `MTU`, refreshing the interface index and retrying once if it went stale
+    fn get_mtu_of_family(&self, is_ipv6: bool) -> Result<usize, Error> {
+        match util::get_mtu_by_index(self.index(), is_ipv6) {
+            Ok(mtu) => Ok(mtu as _),
+            Err(e) => {
+                log::debug!("Failed to get MTU: \"{}\", refreshing the interface index", e);
+                self.refresh()?;
+                Ok(util::get_mtu_by_index(self.index(), is_ipv6)? as _)
+            }
+        }
+    }
+
+    /// Returns whether the adapter accepts IPv6 router advertisements
+    pub fn get_router_discovery(&self) -> Result<bool, Error> {
+        let row = util::get_ip_interface_entry(&self.luid, true)?;
+        Ok(row.RouterDiscoveryBehavior != RouterDiscoveryDisabled)
+    }
+
+    /// Enables or disables acceptance of IPv6 router advertisements on this adapter.
+    /// Disabling it prevents routers on the tunnel from injecting routes or prefixes.
+    pub fn set_router_discovery(&self, enabled: bool) -> Result<(), Error> {
+        let mut row = util::get_ip_interface_entry(&self.luid, true)?;
+        row.RouterDiscoveryBehavior = if enabled {
+            RouterDiscoveryEnabled
+        } else {
+            RouterDiscoveryDisabled
+        };
+        util::set_ip_interface_entry(&mut row, &format!("router_discovery={}", enabled))?;
+        Ok(())
+    }
+
+    /// Returns the `(send, receive)` weak host model settings of the given address family, see
+    /// [`Adapter::set_weak_host`]
+    pub fn get_weak_host(&self, family: AddressFamily) -> Result<(bool, bool), Error> {
+        let row = util::get_ip_interface_entry(&self.luid, family == AddressFamily::Ipv6)?;
+        Ok((row.WeakHostSend != 0, row.WeakHostReceive != 0))
+    }
+
+    /// Sets the weak host model of the given address family. With weak host `receive` the adapter
+    /// accepts packets destined to addresses of other interfaces, and with weak host `send` it
+    /// sends packets with source addresses of other interfaces, as some transparent proxy setups
+    /// with asymmetric routing need.
+    pub fn set_weak_host(&self, send: bool, receive: bool, family: AddressFamily) -> Result<(), Error> {
+        let mut row = util::get_ip_interface_entry(&self.luid, family == AddressFamily::Ipv6)?;
+        row.WeakHostSend = send as _;
+        row.WeakHostReceive = receive as _;
+        let change = format!("weak_host_send={} weak_host_receive={}", send, receive);
+        util::set_ip_interface_entry(&mut row, &change)?;
+        Ok(())
+    }
+
+    /// Returns the number of IPv6 Duplicate Address Detection probes sent for new addresses
+    pub fn get_dad_transmits(&self) -> Result<u32, Error> {
+        Ok(util::get_ip_interface_entry(&self.luid, true)?.DadTransmits)
+    }
+
+    /// Sets the number of IPv6 Duplicate Address Detection probes sent for new addresses.
+    /// With 0 there is no detection, so addresses are usable right away instead of staying
+    /// tentative, which is fine on a point-to-point tunnel.
+    pub fn set_dad_transmits(&self, count: u32) -> Result<(), Error> {
+        let mut row = util::get_ip_interface_entry(&self.luid, true)?;
+        row.DadTransmits = count;
+        util::set_ip_interface_entry(&mut row, &format!("dad_transmits={}", count))?;
+        Ok(())
+    }
+
+    /// Returns the IPv6 `(managed, other_stateful)` autoconfiguration flags of this adapter,
+    /// i.e. whether addresses and other settings are obtained through DHCPv6
+    pub fn get_managed_address_config(&self) -> Result<(bool, bool), Error> {
+        let row = util::get_ip_interface_entry(&self.luid, true)?;
+        Ok((
+            row.ManagedAddressConfigurationSupported != 0,
+            row.OtherStatefulConfigurationSupported != 0,
+        ))
+    }
+
+    /// Sets the IPv6 `managed` (DHCPv6 addresses) and `other_stateful` (DHCPv6 other settings)
+    /// autoconfiguration flags of this adapter
+    pub fn set_managed_address_config(&self, managed: bool, other_stateful: bool) -> Result<(), Error> {
+        let mut row = util::get_ip_interface_entry(&self.luid, true)?;
+        row.ManagedAddressConfigurationSupported = managed as _;
+        row.OtherStatefulConfigurationSupported = other_stateful as _;
+        let change = format!("managed={} other_stateful={}", managed, other_stateful);
+        util::set_ip_interface_entry(&mut row, &change)?;
+        Ok(())
+    }
+
+    /// Returns the network category of this adapter's connection, using the Network List Manager
+    /// (`INetworkListManager`), or PowerShell's `Get-NetConnectionProfile` if that fails.
+    ///
+    /// Windows only creates the connection profile once the adapter is up and has an address.
+    pub fn get_network_category(&self) -> Result<NetworkCategory, Error> {
+        match crate::network_list::get_network_category(self.get_guid()) {
+            Ok(category) => Ok(category),
+            Err(e) => {
+                log::debug!(
+                    "Failed to get network category in first attempt: \"{}\", try another...",
+                    e
+                );
+                self.get_network_category_cmd()
+            }
+        }
+    }
+
+    fn get_network_category_cmd(&self) -> Result<NetworkCategory, Error> {
+        // command line: `powershell -NoProfile -Command "(Get-NetConnectionProfile -InterfaceIndex 12).NetworkCategory"`
+        let script = format!(
+            "(Get-NetConnectionProfile -InterfaceIndex {}).NetworkCategory",
+            self.index()
+        );
+        let out = util::run_command("powershell", &["-NoProfile", "-NonInteractive", "-Command", &script])?;
+        String::from_utf8_lossy(&out).parse()
+    }
+
+    /// Sets the network category of this adapter's connection, using the Network List Manager
+    /// (`INetworkListManager`), or PowerShell's `Set-NetConnectionProfile` if that fails. WinTun
+    /// adapters usually start out as [`NetworkCategory::Public`], under which Windows Firewall
+    /// blocks most inbound traffic.
+    ///
+    /// Requires administrator privileges, and like [`Adapter::get_network_category`] a connection
+    /// profile to exist. [`NetworkCategory::DomainAuthenticated`] cannot be set.
+    pub fn set_network_category(&self, category: NetworkCategory) -> Result<(), Error> {
+        if category == NetworkCategory::DomainAuthenticated {
+            return Err("The DomainAuthenticated network category cannot be set manually".into());
+        }
+        if let Err(e) = crate::network_list::set_network_category(self.get_guid(), category) {
+            log::debug!(
+                "Failed to set network category in first attempt: \"{}\", try another...",
+                e
+            );
+            // command line: `powershell -NoProfile -Command "Set-NetConnectionProfile -InterfaceIndex 12 -NetworkCategory Private"`
+            let script = format!(
+                "Set-NetConnectionProfile -InterfaceIndex {} -NetworkCategory {}",
+                self.index(),
+                category.as_str()
+            );
+            util::run_command("powershell", &["-NoProfile", "-NonInteractive", "-Command", &script])?;
+        }
+        Ok(())
     }
 
     /// Returns the Win32 interface index of this adapter. Useful for specifying the interface
     /// when executing `netsh interface ip` commands
     pub fn get_adapter_index(&self) -> Result<u32, Error> {
-        Ok(self.index)
+        Ok(self.index())
+    }
+
+    /// Re-resolves the interface index of this adapter from its LUID, which stays the same for
+    /// the lifetime of the adapter, and updates the cached index.
+    ///
+    /// Windows can assign a new index to an interface, e.g. after a resume or a driver reinstall,
+    /// which makes the calls using the index fail or act on another interface.
+    pub fn refresh(&self) -> Result<(), Error> {
+        let index = crate::ffi::luid_to_index(&self.luid)?;
+        let previous = self.index.swap(index, Ordering::Relaxed);
+        if previous != index {
+            log::debug!("Interface index of adapter changed from {} to {}", previous, index);
+        }
+        Ok(())
+    }
+
+    fn index(&self) -> u32 {
+        self.index.load(Ordering::Relaxed)
+    }
+
+    /// Returns the netmask [`Adapter::set_address`] uses when the adapter has no IPv4 address yet
+    pub fn default_netmask() -> Ipv4Addr {
+        Ipv4Addr::from(DEFAULT_IPV4_NETMASK.load(Ordering::Relaxed))
+    }
+
+    /// Sets the netmask [`Adapter::set_address`] uses when the adapter has no IPv4 address yet,
+    /// `255.255.255.0` by default. Fails if `mask` is not a contiguous netmask.
+    pub fn set_default_netmask(mask: Ipv4Addr) -> Result<(), Error> {
+        let bits = u32::from(mask);
+        if bits.leading_ones() + bits.trailing_zeros() != 32 {
+            return Err(format!("{} is not a valid netmask", mask).into());
+        }
+        DEFAULT_IPV4_NETMASK.store(bits, Ordering::Relaxed);
+        Ok(())
     }
 
     /// Sets the IP address for this adapter, using command `netsh`.
+    ///
+    /// The netmask of the current IPv4 address is kept, if there is none
+    /// [`Adapter::default_netmask`] is used. See [`Adapter::set_address_with_prefix`] to give it.
     pub fn set_address(&self, address: Ipv4Addr) -> Result<(), Error> {
-        let binding = self.get_addresses()?;
-        let old_address = binding.iter().find(|addr| matches!(addr, IpAddr::V4(_)));
-        let mask = match old_address {
-            Some(IpAddr::V4(addr)) => self.get_netmask_of_address(&(*addr).into())?,
-            _ => "255.255.255.0".parse()?,
+        let mask = match self.get_ipv4_address_and_mask()? {
+            Some((_, mask)) => mask,
+            None => Self::default_netmask(),
         };
+        self.set_address_with_netmask(address, mask)
+    }
+
+    /// Sets the IP address for this adapter and the netmask of length `prefix_length`, using
+    /// command `netsh`.
+    pub fn set_address_with_prefix(&self, address: Ipv4Addr, prefix_length: u8) -> Result<(), Error> {
+        let mask = util::ipv4_netmask_for_prefix(prefix_length)?;
+        self.set_address_with_netmask(address, mask)
+    }
+
+    fn set_address_with_netmask(&self, address: Ipv4Addr, mask: Ipv4Addr) -> Result<(), Error> {
         let gateway = self
             .get_gateways()?
             .iter()
             .find(|addr| matches!(addr, IpAddr::V4(_)))
             .cloned();
-        self.set_network_addresses_tuple(address.into(), mask, gateway)?;
+        self.set_network_addresses_tuple(address.into(), mask.into(), gateway)?;
+        Ok(())
+    }
+
+    /// Assigns the IPv6 address `address/prefix_length` to this adapter using
+    /// `CreateUnicastIpAddressEntry`, and adds the default route `::/0` via `gateway` if given.
+    ///
+    /// Unlike [`Adapter::set_address`], this leaves the other addresses of the adapter in place, so
+    /// calling both dual-stacks the adapter. Assigning an address or default route that is already
+    /// present is not an error.
+    pub fn set_address_v6(&self, address: Ipv6Addr, prefix_length: u8, gateway: Option<Ipv6Addr>) -> Result<(), Error> {
+        if prefix_length > 128 {
+            return Err(format!("Invalid IPv6 prefix length {}", prefix_length).into());
+        }
+        self.ensure_address_not_in_use(&address.into())?;
+        match util::add_unicast_address(&self.luid, address.into(), prefix_length) {
+            Err(e) if e.raw_os_error() == Some(ERROR_OBJECT_ALREADY_EXISTS as i32) => {}
+            r => r?,
+        }
+        if let Some(gateway) = gateway {
+            match self.set_default_route_v6(Some(gateway), 0) {
+                Err(e) if e.raw_os_error() == Some(ERROR_OBJECT_ALREADY_EXISTS as i32) => {}
+                r => r?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Assigns the `primary` address and the `secondaries` to this adapter, each given with its
+    /// prefix length, using `CreateUnicastIpAddressEntry`.
+    ///
+    /// The secondary addresses are flagged `SkipAsSource`, so Windows always picks the primary
+    /// as the source of outbound traffic. Addresses already present are kept, with their flag
+    /// updated, and the other addresses of the adapter are left in place.
+    pub fn configure_addresses(&self, primary: (IpAddr, u8), secondaries: &[(IpAddr, u8)]) -> Result<(), Error> {
+        let addresses = std::iter::once((primary, false)).chain(secondaries.iter().map(|addr| (*addr, true)));
+        for ((address, prefix_length), _) in addresses.clone() {
+            let max_prefix_length = if address.is_ipv4() { 32 } else { 128 };
+            if prefix_length > max_prefix_length {
+                return Err(format!("Invalid prefix length {} for {}", prefix_length, address).into());
+            }
+            self.ensure_address_not_in_use(&address)?;
+        }
+        for ((address, prefix_length), skip_as_source) in addresses {
+            match util::add_unicast_address_ex(&self.luid, address, prefix_length, skip_as_source) {
+                Err(e) if e.raw_os_error() == Some(ERROR_OBJECT_ALREADY_EXISTS as i32) => {
+                    util::set_unicast_address_skip_as_source(&self.luid, address, skip_as_source)?;
+                }
+                r => r?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Configures this adapter as a point-to-point link: `local` is assigned as a host address
+    /// (`/32` or `/128`) and an on-link host route to `peer` is added, instead of a subnet route.
+    pub fn set_address_p2p(&self, local: IpAddr, peer: IpAddr) -> Result<(), Error> {
+        if local.is_ipv4() != peer.is_ipv4() {
+            return Err("Local and peer address families differ".into());
+        }
+        self.ensure_address_not_in_use(&local)?;
+        let host_prefix = if local.is_ipv4() { 32 } else { 128 };
+        let ignore_existing = |r: Result<(), Error>| match r {
+            Err(e) if e.raw_os_error() == Some(ERROR_OBJECT_ALREADY_EXISTS as i32) => Ok(()),
+            r => r,
+        };
+        ignore_existing(util::add_unicast_address(&self.luid, local, host_prefix))?;
+        ignore_existing(route::add_route(&self.luid, peer, host_prefix, None, 0))?;
         Ok(())
     }
 
     /// Sets the gateway for this adapter, using command `netsh`.
     pub fn set_gateway(&self, gateway: Option<Ipv4Addr>) -> Result<(), Error> {
-        let binding = self.get_addresses()?;
-        let address = binding.iter().find(|addr| matches!(addr, IpAddr::V4(_)));
-        let address = match address {
-            Some(IpAddr::V4(addr)) => addr,
-            _ => return Err("Unable to find IPv4 address".into()),
-        };
-        let mask = self.get_netmask_of_address(&(*address).into())?;
+        let (address, mask) = self.get_ipv4_address_and_mask()?.ok_or("Unable to find IPv4 address")?;
         let gateway = gateway.map(|addr| addr.into());
-        self.set_network_addresses_tuple((*address).into(), mask, gateway)?;
+        self.set_network_addresses_tuple(address.into(), mask.into(), gateway)?;
         Ok(())
     }
 
     /// Sets the subnet mask for this adapter, using command `netsh`.
     pub fn set_netmask(&self, mask: Ipv4Addr) -> Result<(), Error> {
-        let binding = self.get_addresses()?;
-        let address = binding.iter().find(|addr| matches!(addr, IpAddr::V4(_)));
-        let address = match address {
-            Some(IpAddr::V4(addr)) => addr,
-            _ => return Err("Unable to find IPv4 address".into()),
-        };
+        let (address, _) = self.get_ipv4_address_and_mask()?.ok_or("Unable to find IPv4 address")?;
         let gateway = self
             .get_gateways()?
             .iter()
             .find(|addr| matches!(addr, IpAddr::V4(_)))
             .cloned();
-        self.set_network_addresses_tuple((*address).into(), mask.into(), gateway)?;
+        self.set_network_addresses_tuple(address.into(), mask.into(), gateway)?;
         Ok(())
     }
 
-    /// Sets the DNS servers for this adapter
+    /// Sets the DNS servers for this adapter, splitting them by family, see [`Adapter::set_dns`]
     pub fn set_dns_servers(&self, dns_servers: &[IpAddr]) -> Result<(), Error> {
         let interface = GUID::from_u128(self.get_guid());
         if let Err(e) = util::set_interface_dns_servers(interface, dns_servers) {
@@ -273,12 +1002,150 @@ impl Adapter {
         Ok(())
     }
 
+    /// Sets the IPv4 and IPv6 DNS servers of this adapter, applying each family separately.
+    ///
+    /// The servers of a family whose list is empty are left as they are, and at least one server
+    /// must be given. [`Adapter::set_dns_servers`] is the untyped equivalent.
+    pub fn set_dns(&self, v4: &[Ipv4Addr], v6: &[Ipv6Addr]) -> Result<(), Error> {
+        if v4.is_empty() && v6.is_empty() {
+            return Err("No DNS servers given".into());
+        }
+        let servers = v4
+            .iter()
+            .map(|addr| IpAddr::V4(*addr))
+            .chain(v6.iter().map(|addr| IpAddr::V6(*addr)))
+            .collect::<Vec<_>>();
+        self.set_dns_servers(&servers)
+    }
+
+    /// Sets the DNS search domains of this adapter, the first one also being its connection-specific
+    /// DNS suffix. An empty list clears them.
+    ///
+    /// Before Windows 10 only the connection-specific suffix can be set, with command `wmic`.
+    pub fn set_dns_domains(&self, domains: &[&str]) -> Result<(), Error> {
+        let interface = GUID::from_u128(self.get_guid());
+        if let Err(e) = util::set_interface_dns_domains(interface, domains) {
+            log::debug!("Failed to set DNS domains in first attempt: \"{}\", try another...", e);
+            if domains.len() > 1 {
+                log::warn!("Only the DNS suffix \"{}\" can be set, not the search list", domains[0]);
+            }
+            util::set_interface_dns_domain_via_cmd(self.index(), domains.first().copied().unwrap_or_default())?;
+        }
+        Ok(())
+    }
+
+    /// Sets both the DNS servers and the DNS search domains of this adapter, see
+    /// [`Adapter::set_dns_servers`] and [`Adapter::set_dns_domains`]
+    pub fn set_dns_config(&self, servers: &[IpAddr], domains: &[&str]) -> Result<(), Error> {
+        self.set_dns_servers(servers)?;
+        self.set_dns_domains(domains)
+    }
+
     /// Sets the network addresses of this adapter, including network address, subnet mask, and gateway
+    ///
+    /// The interface metric is left as it was before the call.
     pub fn set_network_addresses_tuple(
         &self,
         address: IpAddr,
         mask: IpAddr,
         gateway: Option<IpAddr>,
+    ) -> Result<(), Error> {
+        self.ensure_address_not_in_use(&address)?;
+        self.preserve_metric(address.is_ipv6(), || {
+            self.set_network_addresses_tuple_cmd(address, mask, gateway)
+        })
+    }
+
+    /// Returns [`Error::AddressInUse`] if `address` is assigned to an interface other than this adapter
+    fn ensure_address_not_in_use(&self, address: &IpAddr) -> Result<(), Error> {
+        let name = self.get_device_name()?;
+        let mut in_use = false;
+        util::get_adapters_addresses(|adapter| {
+            match unsafe { util::win_pstr_to_string(adapter.AdapterName) } {
+                Ok(name_iter) if name_iter == name => return true,
+                Ok(_) => {}
+                Err(err) => {
+                    log::error!("Failed to parse adapter name: {}", err);
+                    return true;
+                }
+            }
+            let mut current_address = adapter.FirstUnicastAddress;
+            while !current_address.is_null() {
+                let unicast = unsafe { &*current_address };
+                if util::retrieve_ipaddr_from_socket_address(&unicast.Address).ok() == Some(*address) {
+                    in_use = true;
+                    return false;
+                }
+                current_address = unicast.Next;
+            }
+            true
+        })?;
+        if in_use {
+            return Err(Error::AddressInUse(*address));
+        }
+        Ok(())
+    }
+
+    /// Returns the IPv4 or IPv6 (`is_ipv6`) interface metric of this adapter, which is added to
+    /// the metric of each of its routes
+    pub fn get_interface_metric(&self, is_ipv6: bool) -> Result<u32, Error> {
+        Ok(util::get_ip_interface_entry(&self.luid, is_ipv6)?.Metric)
+    }
+
+    /// Pins the interface metric of this adapter to `metric` for both address families, turning off
+    /// the automatic metric.
+    ///
+    /// A low metric keeps the routes of the tunnel from being outranked, and a high one keeps its
+    /// default route from covering the one of the physical LAN. The metric is kept by
+    /// [`Adapter::set_address`] and friends. IPv6 is skipped if it is disabled on the adapter.
+    pub fn set_interface_metric(&self, metric: u32) -> Result<(), Error> {
+        use windows_sys::Win32::Foundation::ERROR_NOT_FOUND;
+        for is_ipv6 in [false, true] {
+            let mut row = match util::get_ip_interface_entry(&self.luid, is_ipv6) {
+                Ok(row) => row,
+                Err(e) if is_ipv6 && e.raw_os_error() == Some(ERROR_NOT_FOUND as i32) => {
+                    log::debug!("IPv6 is disabled on the adapter, not setting its metric");
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            row.Metric = metric;
+            row.UseAutomaticMetric = 0;
+            util::set_ip_interface_entry(&mut row, &format!("metric={} automatic_metric=false", metric))?;
+        }
+        Ok(())
+    }
+
+    /// Runs `f`, then restores the interface metric of the given family if `f` changed it,
+    /// since `netsh interface ipv4 set address` may reset the metric of the interface
+    fn preserve_metric<T>(&self, is_ipv6: bool, f: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+        let before = match util::get_ip_interface_entry(&self.luid, is_ipv6) {
+            Ok(row) => row,
+            Err(e) => {
+                log::debug!("Failed to read interface metric, it will not be preserved: {}", e);
+                return f();
+            }
+        };
+        let result = f()?;
+        let mut after = util::get_ip_interface_entry(&self.luid, is_ipv6)?;
+        if after.Metric != before.Metric || after.UseAutomaticMetric != before.UseAutomaticMetric {
+            after.Metric = before.Metric;
+            after.UseAutomaticMetric = before.UseAutomaticMetric;
+            let change = format!(
+                "metric={} automatic_metric={}",
+                before.Metric,
+                before.UseAutomaticMetric != 0
+            );
+            util::set_ip_interface_entry(&mut after, &change)?;
+        }
+        Ok(result)
+    }
+
+    fn set_network_addresses_tuple_cmd(
+        &self,
+        address: IpAddr,
+        mask: IpAddr,
+        gateway: Option<IpAddr>,
     ) -> Result<(), Error> {
         let name = self.get_name()?;
         // command line: `netsh interface ipv4 set address name="YOUR_INTERFACE_NAME" source=static address=IP_ADDRESS mask=SUBNET_MASK gateway=GATEWAY`
@@ -305,9 +1172,48 @@ impl Adapter {
         Ok(())
     }
 
+    /// Resets the IPv4 or IPv6 (`is_ipv6`) configuration of this adapter to DHCP, using command
+    /// `netsh`, discarding any static addresses and DNS servers.
+    ///
+    /// IPv4 addresses are obtained through DHCP again. Since netsh has no DHCP source for IPv6
+    /// addresses, the static IPv6 addresses are deleted and DHCPv6 is enabled with
+    /// [`Adapter::set_managed_address_config`] instead.
+    pub fn reset_to_dhcp(&self, is_ipv6: bool) -> Result<(), Error> {
+        let adapter_name = self.get_name()?;
+        let name = format!("name=\"{}\"", adapter_name);
+        if is_ipv6 {
+            // command line: `netsh interface ipv6 delete address interface="YOUR_INTERFACE_NAME" address=IP_ADDRESS`
+            let interface = format!("interface=\"{}\"", adapter_name);
+            for address in self.get_addresses()? {
+                let IpAddr::V6(v6) = address else {
+                    continue;
+                };
+                if v6.is_unicast_link_local() {
+                    continue;
+                }
+                let address = format!("address={}", v6);
+                util::run_command(
+                    "netsh",
+                    &["interface", "ipv6", "delete", "address", &interface, &address],
+                )?;
+            }
+            self.set_managed_address_config(true, true)?;
+        } else {
+            // command line: `netsh interface ipv4 set address name="YOUR_INTERFACE_NAME" source=dhcp`
+            util::run_command("netsh", &["interface", "ipv4", "set", "address", &name, "source=dhcp"])?;
+        }
+        // command line: `netsh interface ipv4 set dnsservers name="YOUR_INTERFACE_NAME" source=dhcp`
+        let ip_str = if is_ipv6 { "ipv6" } else { "ipv4" };
+        util::run_command(
+            "netsh",
+            &["interface", ip_str, "set", "dnsservers", &name, "source=dhcp"],
+        )?;
+        Ok(())
+    }
+
     /// Returns the IP addresses of this adapter, including IPv4 and IPv6 addresses
     pub fn get_addresses(&self) -> Result<Vec<IpAddr>, Error> {
-        let name = util::guid_to_win_style_string(&GUID::from_u128(self.guid))?;
+        let name = self.get_device_name()?;
 
         let mut adapter_addresses = vec![];
 
@@ -338,9 +1244,112 @@ impl Adapter {
         Ok(adapter_addresses)
     }
 
+    /// Waits until `addr` is no longer assigned to this adapter, polling [`Adapter::get_addresses`].
+    ///
+    /// Removing an address completes asynchronously, so use this before adding the same address
+    /// again, otherwise netsh can fail with "The object already exists". Returns an error of kind
+    /// [`std::io::ErrorKind::TimedOut`] if the address is still present after `timeout`.
+    pub fn wait_for_address_removed(&self, addr: IpAddr, timeout: std::time::Duration) -> Result<(), Error> {
+        let deadline = std::time::Instant::now() + timeout;
+        while self.get_addresses()?.contains(&addr) {
+            if std::time::Instant::now() >= deadline {
+                let msg = format!("Address {} still assigned after {:?}", addr, timeout);
+                return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, msg).into());
+            }
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+        Ok(())
+    }
+
+    /// Waits until this adapter reports the operational status up, which it does once a session is
+    /// started, e.g. before applying routes or DNS servers that are otherwise not always kept.
+    /// Returns an error of kind [`std::io::ErrorKind::TimedOut`] if it is not up after `timeout`.
+    pub fn wait_until_up(&self, timeout: std::time::Duration) -> Result<(), Error> {
+        let deadline = std::time::Instant::now() + timeout;
+        while util::get_if_row2(&self.luid)?.OperStatus != IfOperStatusUp {
+            if std::time::Instant::now() >= deadline {
+                let msg = format!("Adapter not up after {:?}", timeout);
+                return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, msg).into());
+            }
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+        Ok(())
+    }
+
+    /// Returns whether the addresses of this adapter are configured by DHCP (IPv4) or DHCPv6
+    /// (`is_ipv6`), rather than statically like [`Adapter::set_address`] does
+    pub fn is_dhcp_enabled(&self, is_ipv6: bool) -> Result<bool, Error> {
+        use windows_sys::Win32::NetworkManagement::IpHelper::{
+            IP_ADAPTER_DHCP_ENABLED, IP_ADAPTER_IPV6_MANAGE_ADDRESS_CONFIG,
+        };
+        let name = self.get_device_name()?;
+        let flag = if is_ipv6 {
+            IP_ADAPTER_IPV6_MANAGE_ADDRESS_CONFIG
+        } else {
+            IP_ADAPTER_DHCP_ENABLED
+        };
+
+        let mut enabled = None;
+        util::get_adapters_addresses(
+            |adapter| match unsafe { util::win_pstr_to_string(adapter.AdapterName) } {
+                Ok(name_iter) if name_iter == name => {
+                    enabled = Some(unsafe { adapter.Anonymous2.Flags } & flag != 0);
+                    false
+                }
+                Ok(_) => true,
+                Err(err) => {
+                    log::error!("Failed to parse adapter name: {}", err);
+                    true
+                }
+            },
+        )?;
+
+        Ok(enabled.ok_or("Unable to find matching adapter")?)
+    }
+
+    /// Returns every unicast address of this adapter together with its on-link prefix length,
+    /// e.g. `(10.0.0.2, 24)`, in a single enumeration of the system's adapters
+    pub fn get_prefixes(&self) -> Result<Vec<(IpAddr, u8)>, Error> {
+        let name = self.get_device_name()?;
+        let mut prefixes = vec![];
+        util::get_adapters_addresses(|adapter| {
+            let name_iter = match unsafe { util::win_pstr_to_string(adapter.AdapterName) } {
+                Ok(name) => name,
+                Err(err) => {
+                    log::error!("Failed to parse adapter name: {}", err);
+                    return false;
+                }
+            };
+            if name_iter != name {
+                return true;
+            }
+            let mut current_address = adapter.FirstUnicastAddress;
+            while !current_address.is_null() {
+                let address = unsafe { &*current_address };
+                match util::retrieve_ipaddr_from_socket_address(&address.Address) {
+                    Ok(addr) => prefixes.push((addr, address.OnLinkPrefixLength)),
+                    Err(err) => log::error!("Failed to parse address: {}", err),
+                }
+                current_address = address.Next;
+            }
+            false
+        })?;
+        Ok(prefixes)
+    }
+
+    /// Returns the first IPv4 address of this adapter and its subnet mask
+    fn get_ipv4_address_and_mask(&self) -> Result<Option<(Ipv4Addr, Ipv4Addr)>, Error> {
+        for (address, prefix_length) in self.get_prefixes()? {
+            if let IpAddr::V4(address) = address {
+                return Ok(Some((address, util::ipv4_netmask_for_prefix(prefix_length)?)));
+            }
+        }
+        Ok(None)
+    }
+
     /// Returns the gateway addresses of this adapter, including IPv4 and IPv6 addresses
     pub fn get_gateways(&self) -> Result<Vec<IpAddr>, Error> {
-        let name = util::guid_to_win_style_string(&GUID::from_u128(self.guid))?;
+        let name = self.get_device_name()?;
         let mut gateways = vec![];
         util::get_adapters_addresses(|adapter| {
             let name_iter = match unsafe { util::win_pstr_to_string(adapter.AdapterName) } {
@@ -368,9 +1377,100 @@ impl Adapter {
         Ok(gateways)
     }
 
+    /// Returns the IPv4 and IPv6 DNS servers configured on this adapter
+    pub fn get_dns_servers(&self) -> Result<Vec<IpAddr>, Error> {
+        let name = self.get_device_name()?;
+        let mut dns_servers = vec![];
+        util::get_adapters_addresses(|adapter| {
+            let name_iter = match unsafe { util::win_pstr_to_string(adapter.AdapterName) } {
+                Ok(name) => name,
+                Err(err) => {
+                    log::error!("Failed to parse adapter name: {}", err);
+                    return false;
+                }
+            };
+            if name_iter == name {
+                let mut current_dns = adapter.FirstDnsServerAddress;
+                while !current_dns.is_null() {
+                    let dns = unsafe { (*current_dns).Address };
+                    match util::retrieve_ipaddr_from_socket_address(&dns) {
+                        Ok(addr) => dns_servers.push(addr),
+                        Err(err) => {
+                            log::error!("Failed to parse DNS server: {}", err);
+                        }
+                    }
+                    unsafe { current_dns = (*current_dns).Next };
+                }
+            }
+            true
+        })?;
+        Ok(dns_servers)
+    }
+
+    /// Returns all IPv4 and IPv6 routes currently bound to this adapter,
+    /// read from the system routing table via `GetIpForwardTable2`
+    pub fn get_routes(&self) -> Result<Vec<RouteEntry>, Error> {
+        Ok(route::get_routes_by_index(self.index())?)
+    }
+
+    /// Adds a route to `destination/prefix_length` through this adapter, via the gateway `next_hop`,
+    /// or on-link if `None`, using `CreateIpForwardEntry2` rather than netsh.
+    ///
+    /// `metric` is the route metric, the interface metric is added to it to get the effective
+    /// metric. Returns [`Error::RouteAlreadyExists`] if this route is already present.
+    pub fn add_route(
+        &self,
+        destination: IpAddr,
+        prefix_length: u8,
+        next_hop: Option<IpAddr>,
+        metric: u32,
+    ) -> Result<(), Error> {
+        match route::add_route(&self.luid, destination, prefix_length, next_hop, metric) {
+            Err(e) if e.raw_os_error() == Some(ERROR_OBJECT_ALREADY_EXISTS as i32) => Err(Error::RouteAlreadyExists {
+                destination,
+                prefix_length,
+            }),
+            r => r,
+        }
+    }
+
+    /// Removes the routes to `destination/prefix_length` through this adapter, whatever their
+    /// gateway, using `DeleteIpForwardEntry2`. Fails if there is no such route.
+    pub fn remove_route(&self, destination: IpAddr, prefix_length: u8) -> Result<(), Error> {
+        route::delete_routes(&self.luid, destination, prefix_length)
+    }
+
+    /// Adds the IPv6 default route `::/0` to this adapter, via the gateway `via`, or on-link if `None`.
+    ///
+    /// The on-link prefix routes of the adapter's global IPv6 addresses are created as well if
+    /// they are missing, so that the gateway is reachable.
+    pub fn set_default_route_v6(&self, via: Option<Ipv6Addr>, metric: u32) -> Result<(), Error> {
+        for address in self.get_addresses()? {
+            let IpAddr::V6(addr) = address else {
+                continue;
+            };
+            // Skip link-local addresses (fe80::/10), which are always on-link
+            if addr.segments()[0] & 0xffc0 == 0xfe80 {
+                continue;
+            }
+            let IpAddr::V6(mask) = self.get_netmask_of_address(&address)? else {
+                continue;
+            };
+            let prefix = Ipv6Addr::from(u128::from(addr) & u128::from(mask));
+            let prefix_length = u128::from(mask).count_ones() as u8;
+            match route::add_route(&self.luid, prefix.into(), prefix_length, None, metric) {
+                Err(e) if e.raw_os_error() == Some(ERROR_OBJECT_ALREADY_EXISTS as i32) => {}
+                r => r?,
+            }
+        }
+        let default_route = Ipv6Addr::UNSPECIFIED.into();
+        route::add_route(&self.luid, default_route, 0, via.map(IpAddr::V6), metric)?;
+        Ok(())
+    }
+
     /// Returns the subnet mask of the given address
     pub fn get_netmask_of_address(&self, target_address: &IpAddr) -> Result<IpAddr, Error> {
-        let name = util::guid_to_win_style_string(&GUID::from_u128(self.guid))?;
+        let name = self.get_device_name()?;
         let mut subnet_mask = None;
         util::get_adapters_addresses(|adapter| {
             let name_iter = match unsafe { util::win_pstr_to_string(adapter.AdapterName) } {
@@ -399,6 +1499,7 @@ impl Adapter {
                                 match unsafe { ConvertLengthToIpv4Mask(masklength as u32, &mut mask as *mut u32) } {
                                     0 => {}
                                     err => {
+                                        let err = Error::from_win32(err, "ConvertLengthToIpv4Mask");
                                         log::warn!("Failed to convert length to mask: {}", err);
                                         return false;
                                     }
@@ -425,17 +1526,135 @@ impl Adapter {
     }
 }
 
+/// How long [`AdapterBuilder::build`] waits for the new adapter to be up
+const ADAPTER_UP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Builder creating an adapter, configuring it and starting its session in one step, see
+/// [`Adapter::builder`]
+#[derive(Debug, Clone)]
+pub struct AdapterBuilder {
+    name: String,
+    tunnel_type: String,
+    guid: Option<u128>,
+    ring_capacity: u32,
+    /// The `MTU`, of both families or of the given one
+    mtu: Option<(usize, Option<AddressFamily>)>,
+    address: Option<Ipv4Addr>,
+    netmask: Option<Ipv4Addr>,
+    gateway: Option<Ipv4Addr>,
+    dns: Vec<IpAddr>,
+}
+
+impl AdapterBuilder {
+    /// Sets the name of the adapter
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    /// Sets the tunnel type of the adapter, defaults to `"WinTun"`
+    pub fn tunnel_type(mut self, tunnel_type: &str) -> Self {
+        self.tunnel_type = tunnel_type.to_string();
+        self
+    }
+
+    /// Sets the GUID of the adapter, see [`Adapter::create`]
+    pub fn guid(mut self, guid: u128) -> Self {
+        self.guid = Some(guid);
+        self
+    }
+
+    /// Sets the ring buffer size of the session, defaults to [`crate::MAX_RING_CAPACITY`]
+    pub fn ring_capacity(mut self, capacity: u32) -> Self {
+        self.ring_capacity = capacity;
+        self
+    }
+
+    /// Sets the `MTU` of the adapter, see [`Adapter::set_mtu`], which fails with
+    /// [`Error::MtuTooSmall`] below 1280. Use [`AdapterBuilder::mtu_for`] to set only the IPv4 `MTU`.
+    pub fn mtu(mut self, mtu: usize) -> Self {
+        self.mtu = Some((mtu, None));
+        self
+    }
+
+    /// Sets the `MTU` of one address family of the adapter, see [`Adapter::set_mtu_for`]
+    pub fn mtu_for(mut self, mtu: usize, family: AddressFamily) -> Self {
+        self.mtu = Some((mtu, Some(family)));
+        self
+    }
+
+    /// Sets the IPv4 address of the adapter
+    pub fn address(mut self, address: Ipv4Addr) -> Self {
+        self.address = Some(address);
+        self
+    }
+
+    /// Sets the netmask of the address, defaults to [`Adapter::default_netmask`]
+    pub fn netmask(mut self, netmask: Ipv4Addr) -> Self {
+        self.netmask = Some(netmask);
+        self
+    }
+
+    /// Sets the IPv4 gateway of the adapter
+    pub fn gateway(mut self, gateway: Ipv4Addr) -> Self {
+        self.gateway = Some(gateway);
+        self
+    }
+
+    /// Sets the DNS servers of the adapter, see [`Adapter::set_dns_servers`]
+    pub fn dns(mut self, dns_servers: &[IpAddr]) -> Self {
+        self.dns = dns_servers.to_vec();
+        self
+    }
+
+    /// Creates the adapter, starts the session and waits for the adapter to be up, see
+    /// [`Adapter::wait_until_up`], then applies the address, `MTU` and DNS servers.
+    ///
+    /// The settings are checked before the adapter is created. If a later step fails, the session
+    /// is ended and the newly created adapter is closed, which removes it again.
+    pub fn build(self, wintun: &Wintun) -> Result<(Arc<Adapter>, Arc<Session>), Error> {
+        Adapter::validate_capacity(self.ring_capacity)?;
+        if self.address.is_none() && (self.netmask.is_some() || self.gateway.is_some()) {
+            return Err("Netmask or gateway given without an address".into());
+        }
+        // On failure the session and adapter are dropped on return, which removes the adapter
+        let adapter = Adapter::create(wintun, &self.name, &self.tunnel_type, self.guid)?;
+        let session = adapter.start_session(self.ring_capacity)?;
+        adapter.wait_until_up(ADAPTER_UP_TIMEOUT)?;
+        if let Some(address) = self.address {
+            let netmask = self.netmask.unwrap_or_else(Adapter::default_netmask);
+            let gateway = self.gateway.map(IpAddr::V4);
+            adapter.set_network_addresses_tuple(address.into(), netmask.into(), gateway)?;
+        }
+        match self.mtu {
+            Some((mtu, Some(family))) => adapter.set_mtu_for(mtu, family)?,
+            Some((mtu, None)) => adapter.set_mtu(mtu)?,
+            None => {}
+        }
+        if !self.dns.is_empty() {
+            adapter.set_dns_servers(&self.dns)?;
+        }
+        Ok((adapter, session))
+    }
+}
+
 impl Drop for Adapter {
     fn drop(&mut self) {
         let _name = self.get_name();
+        let context = _name.as_deref().map(crate::log::log_context);
         //Close adapter on drop
         //This is why we need an Arc of wintun
         unsafe { self.wintun.WintunCloseAdapter(self.adapter.0) };
+        drop(context);
         self.adapter = UnsafeHandle(ptr::null_mut());
+        #[cfg(debug_assertions)]
+        crate::LIVE_ADAPTERS.fetch_sub(1, Ordering::SeqCst);
         #[cfg(feature = "winreg")]
         if let Ok(name) = _name {
             // Delete registry related to network card
-            _ = delete_adapter_info_from_reg(&name);
+            if let Err(e) = delete_adapter_info_from_reg(&name) {
+                log::trace!("Skipped registry cleanup of adapter \"{}\": {}", name, e);
+            }
         }
     }
 }
@@ -445,6 +1664,8 @@ impl Drop for Adapter {
 pub(crate) fn delete_adapter_info_from_reg(dev_name: &str) -> std::io::Result<()> {
     use winreg::{enums::HKEY_LOCAL_MACHINE, enums::KEY_ALL_ACCESS, RegKey};
     let hklm = RegKey::predef(HKEY_LOCAL_MACHINE);
+    // Opening the parent keys with full access fails up front without administrator privileges,
+    // the cleanup is best effort so the per-key failures below are only traced
     let profiles_key = hklm.open_subkey_with_flags(
         "SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion\\NetworkList\\Profiles",
         KEY_ALL_ACCESS,
@@ -456,12 +1677,12 @@ pub(crate) fn delete_adapter_info_from_reg(dev_name: &str) -> std::io::Result<()
             Ok(profile_name) => {
                 if dev_name == profile_name {
                     match profiles_key.delete_subkey_all(&sub_key_name) {
-                        Ok(_) => log::info!("Successfully deleted Profiles sub_key: {}", sub_key_name),
-                        Err(e) => log::warn!("Failed to delete Profiles sub_key {}: {}", sub_key_name, e),
+                        Ok(_) => log::debug!("Successfully deleted Profiles sub_key: {}", sub_key_name),
+                        Err(e) => log::trace!("Failed to delete Profiles sub_key {}: {}", sub_key_name, e),
                     }
                 }
             }
-            Err(e) => log::warn!("Failed to read ProfileName for sub_key {}: {}", sub_key_name, e),
+            Err(e) => log::trace!("Failed to read ProfileName for sub_key {}: {}", sub_key_name, e),
         }
     }
     let unmanaged_key = hklm.open_subkey_with_flags(
@@ -474,13 +1695,88 @@ pub(crate) fn delete_adapter_info_from_reg(dev_name: &str) -> std::io::Result<()
             Ok(description) => {
                 if dev_name == description {
                     match unmanaged_key.delete_subkey_all(&sub_key_name) {
-                        Ok(_) => log::info!("Successfully deleted Unmanaged sub_key: {}", sub_key_name),
-                        Err(e) => log::warn!("Failed to delete Unmanaged sub_key {}: {}", sub_key_name, e),
+                        Ok(_) => log::debug!("Successfully deleted Unmanaged sub_key: {}", sub_key_name),
+                        Err(e) => log::trace!("Failed to delete Unmanaged sub_key {}: {}", sub_key_name, e),
                     }
                 }
             }
-            Err(e) => log::warn!("Failed to read Description for sub_key {}: {}", sub_key_name, e),
+            Err(e) => log::trace!("Failed to read Description for sub_key {}: {}", sub_key_name, e),
         }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "uuid")]
+    fn uuid_round_trips_through_guid() {
+        let uuid: uuid::Uuid = "6ba7b810-9dad-11d1-80b4-00c04fd430c8".parse().unwrap();
+        let guid = GUID::from_u128(uuid.as_u128());
+        assert_eq!(guid.data1, 0x6ba7b810);
+        assert_eq!(guid.data2, 0x9dad);
+        assert_eq!(guid.data3, 0x11d1);
+        assert_eq!(guid.data4, [0x80, 0xb4, 0x00, 0xc0, 0x4f, 0xd4, 0x30, 0xc8]);
+        assert_eq!(uuid::Uuid::from_u128(util::win_guid_to_u128(&guid)), uuid);
+    }
+
+    #[test]
+    #[ignore = "requires administrator privileges"]
+    fn wait_until_up_before_set_address() {
+        let dll_path = crate::get_wintun_bin_pattern_path().unwrap();
+        let wintun = unsafe { crate::load_from_path(dll_path) }.unwrap();
+        let adapter = Adapter::create(&wintun, "UpTest", "Test", None).unwrap();
+        let _session = adapter.start_session(crate::MAX_RING_CAPACITY).unwrap();
+
+        adapter.wait_until_up(std::time::Duration::from_secs(5)).unwrap();
+        adapter.set_address("10.28.14.2".parse().unwrap()).unwrap();
+    }
+
+    #[test]
+    #[ignore = "requires administrator privileges"]
+    fn set_address_preserves_metric() {
+        let dll_path = crate::get_wintun_bin_pattern_path().unwrap();
+        let wintun = unsafe { crate::load_from_path(dll_path) }.unwrap();
+        let adapter = Adapter::create(&wintun, "MetricTest", "Test", None).unwrap();
+
+        let before = util::get_ip_interface_entry(&adapter.get_luid(), false).unwrap();
+        adapter.set_address("10.28.13.2".parse().unwrap()).unwrap();
+        let after = util::get_ip_interface_entry(&adapter.get_luid(), false).unwrap();
+
+        assert_eq!(before.Metric, after.Metric);
+        assert_eq!(before.UseAutomaticMetric, after.UseAutomaticMetric);
+    }
+
+    #[test]
+    #[ignore = "requires administrator privileges"]
+    fn set_mixed_family_dns_servers() {
+        let dll_path = crate::get_wintun_bin_pattern_path().unwrap();
+        let wintun = unsafe { crate::load_from_path(dll_path) }.unwrap();
+        let adapter = Adapter::create(&wintun, "DnsTest", "Test", None).unwrap();
+        let _session = adapter.start_session(crate::MAX_RING_CAPACITY).unwrap();
+
+        let v4: IpAddr = "10.28.15.53".parse().unwrap();
+        let v6: IpAddr = "fd00:28:15::53".parse().unwrap();
+        adapter.set_dns_servers(&[v4, v6]).unwrap();
+
+        let dns_servers = adapter.get_dns_servers().unwrap();
+        assert!(dns_servers.contains(&v4), "{:?}", dns_servers);
+        assert!(dns_servers.contains(&v6), "{:?}", dns_servers);
+    }
+
+    #[test]
+    #[ignore = "requires administrator privileges"]
+    fn non_ascii_name_round_trips() {
+        let dll_path = crate::get_wintun_bin_pattern_path().unwrap();
+        let wintun = unsafe { crate::load_from_path(dll_path) }.unwrap();
+        let adapter = Adapter::create(&wintun, "隧道", "Test", None).unwrap();
+        assert_eq!(adapter.get_name().unwrap(), "隧道");
+        assert!(Adapter::exists("隧道"));
+        adapter.get_addresses().unwrap();
+
+        adapter.set_name("隧道-Ünïcödé").unwrap();
+        assert_eq!(adapter.get_name().unwrap(), "隧道-Ünïcödé");
+    }
+}
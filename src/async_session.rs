@@ -1,14 +1,21 @@
-use crate::{handle::UnsafeHandle, session::Session};
-use futures::{AsyncRead, AsyncWrite};
+use crate::{handle::UnsafeHandle, packet::Packet, session::Session};
+use futures::{AsyncRead, AsyncWrite, Sink};
 use std::future::Future;
 use std::pin::Pin;
-use std::sync::{Arc, Mutex};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
 use std::task::{Context, Poll};
 use windows_sys::Win32::{
-    Foundation::{FALSE, HANDLE, WAIT_ABANDONED_0, WAIT_EVENT, WAIT_OBJECT_0},
-    System::Threading::{WaitForMultipleObjects, INFINITE},
+    Foundation::{ERROR_BUFFER_OVERFLOW, FALSE, HANDLE, WAIT_ABANDONED_0, WAIT_EVENT, WAIT_OBJECT_0},
+    System::Threading::{SetEvent, WaitForMultipleObjects, WaitForSingleObject, INFINITE},
 };
 
+/// How long [`AsyncSession::writable`] and the [`Sink`] implementation wait after the send ring
+/// was found full
+const SEND_RETRY_INTERVAL_MS: u32 = 1;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum WaitingStopReason {
     Shutdown,
@@ -22,10 +29,25 @@ enum ReadState {
     Closed,
 }
 
-#[derive(Clone)]
 pub struct AsyncSession {
     session: Arc<Session>,
     read_state: ReadState,
+    /// Packet accepted by the [`Sink`] implementation while the send ring was full
+    pending_send: Option<Vec<u8>>,
+    /// Wait before retrying `pending_send`, resolving to `true` if the session was shut down
+    send_backoff: Option<blocking::Task<bool>>,
+}
+
+impl Clone for AsyncSession {
+    fn clone(&self) -> Self {
+        Self {
+            session: self.session.clone(),
+            read_state: self.read_state.clone(),
+            // A held back packet belongs to this instance only, it must not be sent twice
+            pending_send: None,
+            send_backoff: None,
+        }
+    }
 }
 
 impl std::ops::Deref for AsyncSession {
@@ -41,6 +63,8 @@ impl From<Arc<Session>> for AsyncSession {
         Self {
             session,
             read_state: ReadState::Idle,
+            pending_send: None,
+            send_backoff: None,
         }
     }
 }
@@ -58,6 +82,11 @@ impl AsyncSession {
         }
     }
 
+    /// Waits up to `timeout` milliseconds for the session to be shut down, returns whether it was
+    fn wait_for_shutdown(shutdown_event: UnsafeHandle<HANDLE>, timeout: u32) -> bool {
+        unsafe { WaitForSingleObject(shutdown_event.0, timeout) == WAIT_OBJECT_0 }
+    }
+
     pub async fn recv(&self, buf: &mut [u8]) -> std::io::Result<usize> {
         loop {
             match self.session.try_receive() {
@@ -84,10 +113,125 @@ impl AsyncSession {
         }
     }
 
+    /// Receives the next packet, waiting until one is available, without copying it.
+    ///
+    /// Unlike [`AsyncSession::recv`] the whole packet is returned no matter its size. The packet
+    /// occupies a slot of the receive ring until it is dropped, so drop it as soon as possible,
+    /// otherwise the ring fills up and the driver starts dropping incoming packets.
+    pub async fn receive_packet(&self) -> std::io::Result<Packet> {
+        loop {
+            match self.session.try_receive() {
+                Ok(Some(packet)) => return Ok(packet),
+                Ok(None) => self.readable().await?,
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    /// Waits until a packet is available to read, without consuming it, analogous to
+    /// `TcpStream::readable`. Intended for `select!`-style code, followed by
+    /// [`Session::try_receive`].
+    ///
+    /// Readiness is edge triggered and this doesn't look at the receive ring, since WinTun can't
+    /// check for a packet without taking it. Callers must drain the ring with `try_receive` until
+    /// it returns `Ok(None)` before every call, including the first one, otherwise this can wait
+    /// forever while packets are already queued. The wakeup may be spurious, in which case
+    /// `try_receive` returns `Ok(None)` right away. Returns an error once the session is shut down.
+    pub async fn readable(&self) -> std::io::Result<()> {
+        let read_event = self.session.get_read_wait_event()?;
+        let shutdown_event = self.session.shutdown_event.get_handle();
+        match blocking::unblock(move || Self::wait_for_read(read_event, shutdown_event)).await {
+            WaitingStopReason::Shutdown => Err(crate::Error::ShuttingDown.into()),
+            WaitingStopReason::Ready => {
+                // The read event is auto reset, signal it again so the readiness isn't lost for a
+                // concurrent `recv` or the next wait
+                unsafe { SetEvent(read_event.0) };
+                Ok(())
+            }
+        }
+    }
+
+    /// Waits until the send ring probably has room for a packet, for backpressure-aware senders
+    /// using `select!`-style code.
+    ///
+    /// WinTun has no event for free send ring space, so this resolves right away unless a send
+    /// failed because the ring was full since the last call, in which case it waits a short
+    /// interval for the driver to consume packets. The next send may still find the ring full,
+    /// then wait again. Returns an error once the session is shut down.
+    pub async fn writable(&self) -> std::io::Result<()> {
+        if !self.session.send_ring_full.swap(false, Ordering::AcqRel) {
+            return Ok(());
+        }
+        let shutdown_event = self.session.shutdown_event.get_handle();
+        if blocking::unblock(move || Self::wait_for_shutdown(shutdown_event, SEND_RETRY_INTERVAL_MS)).await {
+            return Err(crate::Error::ShuttingDown.into());
+        }
+        Ok(())
+    }
+
     pub async fn send(&self, buf: &[u8]) -> std::io::Result<usize> {
         self.internal_send(buf)
     }
 
+    /// Sends `buf` as one packet, returning `Ok(false)` if the send ring is currently full
+    fn try_send_packet(&self, buf: &[u8]) -> std::io::Result<bool> {
+        if buf.len() > crate::MAX_IP_PACKET_SIZE as usize {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Packet too large",
+            ));
+        }
+        match self.session.allocate_send_packet(buf.len() as _) {
+            Ok(mut packet) => {
+                packet.bytes_mut().copy_from_slice(buf);
+                self.session.send_packet(packet);
+                Ok(true)
+            }
+            Err(crate::Error::Io(e)) if e.raw_os_error() == Some(ERROR_BUFFER_OVERFLOW as i32) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Retries sending the packet held back by the [`Sink`] implementation, if any
+    fn poll_send_pending(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        loop {
+            if let Some(backoff) = self.send_backoff.as_mut() {
+                let shut_down = futures::ready!(Pin::new(backoff).poll(cx));
+                self.send_backoff = None;
+                if shut_down {
+                    return Poll::Ready(Err(crate::Error::ShuttingDown.into()));
+                }
+            }
+            let Some(buf) = self.pending_send.take() else {
+                return Poll::Ready(Ok(()));
+            };
+            if self.try_send_packet(&buf)? {
+                return Poll::Ready(Ok(()));
+            }
+            self.pending_send = Some(buf);
+            // WinTun has no event for free send ring space, so wait a little off this thread
+            // before retrying, like `writable` does
+            let shutdown_event = self.session.shutdown_event.get_handle();
+            self.send_backoff = Some(blocking::unblock(move || {
+                Self::wait_for_shutdown(shutdown_event, SEND_RETRY_INTERVAL_MS)
+            }));
+        }
+    }
+
+    fn sink_start_send(&mut self, item: &[u8]) -> std::io::Result<()> {
+        debug_assert!(self.pending_send.is_none(), "start_send called without poll_ready");
+        if !self.try_send_packet(item)? {
+            self.pending_send = Some(item.to_vec());
+        }
+        Ok(())
+    }
+
+    fn sink_poll_close(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        futures::ready!(self.poll_send_pending(cx))?;
+        self.session.shutdown()?;
+        Poll::Ready(Ok(()))
+    }
+
     fn internal_send(&self, buf: &[u8]) -> std::io::Result<usize> {
         let packet = self.session.allocate_send_packet(buf.len() as _)?;
         packet.bytes.copy_from_slice(buf);
@@ -96,8 +240,9 @@ impl AsyncSession {
     }
 }
 
-impl AsyncRead for AsyncSession {
-    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context, buf: &mut [u8]) -> Poll<std::io::Result<usize>> {
+impl AsyncSession {
+    /// Reads one packet into `buf`, `Ok(0)` once the session is shut down
+    fn poll_read_packet(&mut self, cx: &mut Context, buf: &mut [u8]) -> Poll<std::io::Result<usize>> {
         use std::io::{Error, ErrorKind::Other};
         loop {
             match &mut self.read_state {
@@ -148,6 +293,43 @@ impl AsyncRead for AsyncSession {
     }
 }
 
+impl AsyncRead for AsyncSession {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context, buf: &mut [u8]) -> Poll<std::io::Result<usize>> {
+        self.get_mut().poll_read_packet(cx, buf)
+    }
+}
+
+/// Each read returns exactly one packet, and EOF once the session is shut down
+#[cfg(feature = "tokio")]
+impl tokio::io::AsyncRead for AsyncSession {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let size = futures::ready!(self.get_mut().poll_read_packet(cx, buf.initialize_unfilled()))?;
+        buf.advance(size);
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Each write is sent as exactly one packet
+#[cfg(feature = "tokio")]
+impl tokio::io::AsyncWrite for AsyncSession {
+    fn poll_write(self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        Poll::Ready(Ok(self.internal_send(buf)?))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        self.session.shutdown()?;
+        Poll::Ready(Ok(()))
+    }
+}
+
 impl AsyncWrite for AsyncSession {
     fn poll_write(self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
         Poll::Ready(Ok(self.internal_send(buf)?))
@@ -162,3 +344,93 @@ impl AsyncWrite for AsyncSession {
         Poll::Ready(Ok(()))
     }
 }
+
+/// Each item is sent as exactly one WinTun packet. `poll_ready` is pending while the send ring is full
+impl Sink<Vec<u8>> for AsyncSession {
+    type Error = std::io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().poll_send_pending(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Vec<u8>) -> Result<(), Self::Error> {
+        self.get_mut().sink_start_send(&item)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().poll_send_pending(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().sink_poll_close(cx)
+    }
+}
+
+/// Each item is sent as exactly one WinTun packet. `poll_ready` is pending while the send ring is full
+impl Sink<&[u8]> for AsyncSession {
+    type Error = std::io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().poll_send_pending(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: &[u8]) -> Result<(), Self::Error> {
+        self.get_mut().sink_start_send(item)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().poll_send_pending(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().sink_poll_close(cx)
+    }
+}
+
+/// How [`Session::forward_to`] delimits packets in the byte stream it writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framing {
+    /// Packets are written back to back, the receiver has to parse the IP headers to split them
+    Raw,
+    /// Each packet is preceded by its length as a big endian `u16`
+    LengthPrefixed,
+}
+
+impl Session {
+    /// Reads packets from this session and writes them into `sink` until `running` is cleared or
+    /// the session is shut down, e.g. to carry the tunnel's traffic over a QUIC stream.
+    ///
+    /// Each packet is copied out and its ring slot released before it is written, so a slow sink
+    /// never holds up the receive ring. `sink` is flushed before returning.
+    pub async fn forward_to<W>(
+        self: &Arc<Self>,
+        mut sink: W,
+        running: Arc<AtomicBool>,
+        framing: Framing,
+    ) -> std::io::Result<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        use futures::AsyncWriteExt;
+        // Wake up regularly to observe `running` being cleared
+        const POLL_INTERVAL_MS: u32 = 100;
+        while running.load(Ordering::Relaxed) {
+            let packet = match self.with_next_packet(|bytes| bytes.to_vec())? {
+                Some(packet) => packet,
+                None => {
+                    let session = self.clone();
+                    match blocking::unblock(move || session.wait_read_timeout(POLL_INTERVAL_MS)).await {
+                        Ok(_) => continue,
+                        Err(crate::Error::ShuttingDown) => break,
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+            };
+            if framing == Framing::LengthPrefixed {
+                sink.write_all(&(packet.len() as u16).to_be_bytes()).await?;
+            }
+            sink.write_all(&packet).await?;
+        }
+        sink.flush().await
+    }
+}
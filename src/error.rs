@@ -18,6 +18,12 @@ pub enum Error {
     #[error("CapacityOutOfRange {0:?}")]
     CapacityOutOfRange(OutOfRangeData<u32>),
 
+    #[error("MtuOutOfRange {0:?}")]
+    MtuOutOfRange(OutOfRangeData<u32>),
+
+    #[error("MTU {requested} is smaller than the minimum {minimum}")]
+    MtuTooSmall { requested: u32, minimum: u32 },
+
     #[error("{0}")]
     String(String),
 
@@ -47,6 +53,60 @@ pub enum Error {
 
     #[error("Session shutting down")]
     ShuttingDown,
+
+    #[error("Unsupported architecture {0}")]
+    UnsupportedArchitecture(String),
+
+    #[error("Address {0} is already assigned to another interface")]
+    AddressInUse(std::net::IpAddr),
+
+    #[error("Route to {destination}/{prefix_length} already exists")]
+    RouteAlreadyExists {
+        destination: std::net::IpAddr,
+        prefix_length: u8,
+    },
+
+    /// A WinTun call failed. `message` is the most severe message WinTun logged for it and `code`
+    /// the Win32 error it left, e.g. `ERROR_ALREADY_EXISTS` when the adapter name is in use
+    #[error("{context} \"{message}\"")]
+    WintunDriver {
+        context: String,
+        level: log::Level,
+        message: String,
+        code: Option<u32>,
+    },
+
+    #[error("{context} failed: {message} (os error {code})")]
+    Win32 {
+        code: u32,
+        message: String,
+        context: String,
+    },
+}
+
+impl Error {
+    /// Builds an [`Error::Win32`] from the Win32 error `code` returned by the function described
+    /// by `context`, with the system message for the code as returned by [`crate::format_message`]
+    pub fn from_win32(code: u32, context: &str) -> Self {
+        let message = match crate::util::format_message(code) {
+            Ok(message) => message.trim().to_string(),
+            Err(_) => format!("Unknown error {:#010x}", code),
+        };
+        Error::Win32 {
+            code,
+            message,
+            context: context.to_string(),
+        }
+    }
+
+    /// Returns the Win32 error code of an OS error, like [`std::io::Error::raw_os_error`]
+    pub fn raw_os_error(&self) -> Option<i32> {
+        match self {
+            Error::Io(e) => e.raw_os_error(),
+            Error::Win32 { code, .. } | Error::WintunDriver { code: Some(code), .. } => Some(*code as i32),
+            _ => None,
+        }
+    }
 }
 
 impl From<String> for Error {
@@ -77,6 +137,10 @@ impl From<Error> for std::io::Error {
     fn from(value: Error) -> Self {
         match value {
             Error::Io(io) => io,
+            // Kept as OS errors, so callers can still check `raw_os_error()`
+            Error::Win32 { code, .. } | Error::WintunDriver { code: Some(code), .. } => {
+                std::io::Error::from_raw_os_error(code as i32)
+            }
             _ => std::io::Error::new(std::io::ErrorKind::Other, value),
         }
     }
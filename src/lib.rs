@@ -9,7 +9,12 @@ mod ffi;
 mod fn_holder;
 mod handle;
 mod log;
+mod network_list;
 mod packet;
+#[cfg(feature = "pcap")]
+mod pcap;
+mod pool;
+mod route;
 mod session;
 mod util;
 #[cfg(feature = "verify_binary_signature")]
@@ -23,21 +28,34 @@ pub(crate) const WINTUN_PROVIDER: &str = "WireGuard LLC";
 mod wintun_raw;
 
 #[cfg(feature = "async")]
-pub use crate::async_session::AsyncSession;
+pub use crate::async_session::{AsyncSession, Framing};
+#[cfg(feature = "pcap")]
+pub use crate::pcap::PcapGuard;
 
 pub use crate::{
-    adapter::Adapter,
+    adapter::{Adapter, AdapterBuilder, AddressFamily, NetworkCategory, ADAPTER_GUID_NAMESPACE},
     error::{BoxError, Error, OutOfRangeData, Result},
     handle::UnsafeHandle,
-    log::{default_logger, reset_logger, set_logger},
+    log::{default_logger, reset_logger, set_log_level_filter, set_logger, set_logger_closure, LogItem, LoggerClosure},
     packet::Packet,
-    session::Session,
-    util::get_active_network_interface_gateways,
+    pool::{PacketPool, PoolRef},
+    route::RouteEntry,
+    session::{MultiReader, PacketIter, PacketReader, PacketWriter, PeekedBytes, Session},
+    util::{
+        find_conflicting_default_routes, get_active_network_interface_gateways,
+        get_active_network_interface_gateways_by_family, get_default_gateways, get_privacy_addresses,
+        set_command_observer, set_privacy_addresses,
+    },
 };
+#[cfg(feature = "enable_inner_logging")]
+pub use log::{drain_logs_filtered, set_log_capacity, DEFAULT_LOG_CAPACITY};
+#[cfg(feature = "winreg")]
+pub use util::detect_conflicting_filter_drivers;
 #[doc(hidden)]
 pub use util::{format_message, get_wintun_bin_pattern_path, run_command, set_adapter_mtu};
 
 pub use windows_sys::Win32::{Foundation::HANDLE, NetworkManagement::Ndis::NET_LUID_LH};
+pub use wintun_raw::{WINTUN_ADAPTER_HANDLE, WINTUN_SESSION_HANDLE};
 
 /// The maximum size of wintun's internal ring buffer (in bytes)
 pub const MAX_RING_CAPACITY: u32 = wintun_raw::WINTUN_MAX_RING_CAPACITY;
@@ -87,14 +105,158 @@ pub unsafe fn load() -> Result<Wintun, Error> {
 ///
 /// For more information see [`libloading`]'s dynamic library safety guarantees: [`libloading`][`libloading::Library::new`]
 pub unsafe fn load_from_path<P>(path: P) -> Result<Wintun, Error>
+where
+    P: AsRef<::std::ffi::OsStr>,
+{
+    verify_dll(&path)?;
+    unsafe { Ok(Arc::new(wintun_raw::wintun::new(path)?)) }
+}
+
+/// Attempts to load the Wintun library from the dll image `bytes`, e.g. embedded in the executable
+/// with `include_bytes!`, so no wintun.dll has to be shipped next to it.
+///
+/// Windows can only load a dll from a file, so the image is written to
+/// `%TEMP%\wintun-bindings-<sha1>\wintun.dll` and loaded like [`load_from_path_secure`]. The file
+/// is reused by later calls with the same image, and is opened without write sharing and checked
+/// against `bytes` before loading, so it can't be swapped in between. Fails if the image was built
+/// for another architecture.
+///
+/// A loaded dll can't be deleted, so the file is removed by [`unload`] where possible, and the
+/// files of other images left behind by earlier processes are removed by the next call.
+///
+/// # Safety
+/// Same as [`load_from_path`], the image is trusted to be a genuine wintun.dll
+pub unsafe fn load_from_memory(bytes: &[u8]) -> Result<Wintun, Error> {
+    use std::{io::Read, os::windows::fs::OpenOptionsExt};
+    use windows_sys::Win32::Storage::FileSystem::FILE_SHARE_READ;
+
+    match util::dll_machine(bytes) {
+        Some(machine) if machine == util::PROCESS_DLL_MACHINE => {}
+        Some(machine) => {
+            let expected = util::PROCESS_DLL_MACHINE;
+            return Err(format!(
+                "The dll is built for machine {:#06x}, expected {:#06x}",
+                machine, expected
+            )
+            .into());
+        }
+        None => return Err("The bytes are not a dll image".into()),
+    }
+
+    let hash = util::sha1(bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    let dir_name = format!("{}{}", MEMORY_DLL_DIR_PREFIX, hash);
+    remove_memory_dll_dirs(|name| name != dir_name);
+    let dir = std::env::temp_dir().join(&dir_name);
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join("wintun.dll");
+
+    let open = || {
+        std::fs::OpenOptions::new()
+            .read(true)
+            .share_mode(FILE_SHARE_READ)
+            .open(&path)
+    };
+    let matches = |file: &mut std::fs::File| -> std::io::Result<bool> {
+        let mut on_disk = Vec::with_capacity(bytes.len());
+        file.read_to_end(&mut on_disk)?;
+        Ok(on_disk == bytes)
+    };
+    // Written to a file of its own first, so no other process ever opens a partially written dll
+    let install = || -> std::io::Result<()> {
+        let temp_path = dir.join(format!("wintun.dll.{}.tmp", std::process::id()));
+        std::fs::write(&temp_path, bytes)?;
+        std::fs::rename(&temp_path, &path).inspect_err(|_| {
+            let _ = std::fs::remove_file(&temp_path);
+        })
+    };
+    let mut file = match open() {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            install()?;
+            open()?
+        }
+        Err(e) => return Err(e.into()),
+    };
+    // Nobody can write to the file while it is open, so what is loaded is what is checked here
+    if !matches(&mut file)? {
+        ::log::debug!("{:?} did not match the dll image, rewriting it", path);
+        drop(file);
+        install()?;
+        file = open()?;
+        if !matches(&mut file)? {
+            return Err(format!("{:?} does not match the dll image", path).into());
+        }
+    }
+    let wintun = unsafe { load_from_path_secure(&path) };
+    drop(file);
+    if wintun.is_ok() {
+        if let Ok(mut dirs) = MEMORY_DLL_DIRS.lock() {
+            dirs.push(dir_name);
+        }
+    }
+    wintun
+}
+
+/// Prefix of the `%TEMP%` directories [`load_from_memory`] writes the dll images to
+const MEMORY_DLL_DIR_PREFIX: &str = "wintun-bindings-";
+
+/// Directories of the dll images loaded by [`load_from_memory`], removed by [`unload`]
+static MEMORY_DLL_DIRS: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+
+/// Removes the directories written by [`load_from_memory`] that `filter` selects. Directories
+/// with a dll still loaded, by this or another process, fail to be removed and are kept.
+fn remove_memory_dll_dirs(filter: impl Fn(&str) -> bool) {
+    let Ok(entries) = std::fs::read_dir(std::env::temp_dir()) else {
+        return;
+    };
+    for entry in entries.filter_map(Result::ok) {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with(MEMORY_DLL_DIR_PREFIX) && filter(&name) {
+            if let Err(e) = std::fs::remove_dir_all(entry.path()) {
+                ::log::trace!("Kept {:?}: {}", entry.path(), e);
+            }
+        }
+    }
+}
+
+/// Attempts to load the Wintun library from the absolute path `path`, guarding against dll planting.
+///
+/// The library is loaded with `LoadLibraryExW` and the flags `LOAD_LIBRARY_SEARCH_DLL_LOAD_DIR |
+/// LOAD_LIBRARY_SEARCH_SYSTEM32`, so the dependencies of wintun.dll are only looked up in the
+/// directory of `path` and in System32, never in the current working directory or `PATH`.
+/// (`LOAD_WITH_ALTERED_SEARCH_PATH` can't be combined with these flags and isn't needed.)
+///
+/// # Safety
+/// See [`load_from_path`], the same caveats about running code of the loaded dll apply.
+pub unsafe fn load_from_path_secure<P>(path: P) -> Result<Wintun, Error>
+where
+    P: AsRef<::std::ffi::OsStr>,
+{
+    use libloading::os::windows::{Library, LOAD_LIBRARY_SEARCH_DLL_LOAD_DIR, LOAD_LIBRARY_SEARCH_SYSTEM32};
+    if !std::path::Path::new(path.as_ref()).is_absolute() {
+        return Err(format!("Path {:?} is not absolute", path.as_ref()).into());
+    }
+    verify_dll(&path)?;
+    let flags = LOAD_LIBRARY_SEARCH_DLL_LOAD_DIR | LOAD_LIBRARY_SEARCH_SYSTEM32;
+    let library = unsafe { Library::load_with_flags(path, flags)? };
+    unsafe { load_from_library(library) }
+}
+
+/// Ensures the dll file has not been tampered with, if the `verify_binary_signature` feature is enabled
+///
+/// # Safety
+/// Same requirements as [`load_from_path`], whose path is resolved here.
+unsafe fn verify_dll<P>(_path: &P) -> Result<(), Error>
 where
     P: AsRef<::std::ffi::OsStr>,
 {
     #[cfg(feature = "verify_binary_signature")]
     {
         use verify_binary_signature::{get_dll_absolute_path, get_signer_name, verify_signature};
-        // Ensure the dll file has not been tampered with.
-        let abs_path = get_dll_absolute_path(&path)?;
+        let abs_path = get_dll_absolute_path(_path)?;
         verify_signature(&abs_path)?;
         let signer_name = get_signer_name(&abs_path)?;
         let wp = WINTUN_PROVIDER;
@@ -102,7 +264,7 @@ where
             return Err(format!("Signer \"{}\" not match \"{}\"", signer_name, wp).into());
         }
     }
-    unsafe { Ok(Arc::new(wintun_raw::wintun::new(path)?)) }
+    Ok(())
 }
 
 /// Attempts to load the Wintun library from an existing [`libloading::Library`].
@@ -122,6 +284,44 @@ where
     unsafe { Ok(Arc::new(wintun_raw::wintun::from_library(library)?)) }
 }
 
+/// Unloads the Wintun library deterministically, e.g. before an updater replaces wintun.dll.
+///
+/// The logger callback is detached first so WinTun can't call into it after unloading.
+/// Fails without unloading if any adapters, sessions or other clones of `wintun` are still alive.
+pub fn unload(wintun: Wintun) -> Result<(), Error> {
+    let count = Arc::strong_count(&wintun);
+    if count > 1 {
+        return Err(format!("Wintun is still in use by {} adapters, sessions or clones", count - 1).into());
+    }
+    log::unset_logger(&wintun);
+    let library = Arc::try_unwrap(wintun).map_err(|_| "Wintun is still in use")?;
+    drop(library);
+    let dirs = MEMORY_DLL_DIRS.lock().map(|mut dirs| std::mem::take(&mut *dirs));
+    if let Ok(dirs) = dirs {
+        remove_memory_dll_dirs(|name| dirs.iter().any(|dir| dir == name));
+    }
+    Ok(())
+}
+
+/// Number of live [`Adapter`] and [`Session`] instances, counted in debug builds only
+#[cfg(debug_assertions)]
+pub(crate) static LIVE_ADAPTERS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+#[cfg(debug_assertions)]
+pub(crate) static LIVE_SESSIONS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+/// Returns the number of `(adapters, sessions)` currently alive in this process, so tests can
+/// assert that everything was dropped after a run. Only available in debug builds.
+#[cfg(debug_assertions)]
+pub fn debug_outstanding_handles() -> (usize, usize) {
+    use std::sync::atomic::Ordering;
+    (
+        LIVE_ADAPTERS.load(Ordering::SeqCst),
+        LIVE_SESSIONS.load(Ordering::SeqCst),
+    )
+}
+
+/// Version of the wintun driver, returned by [`get_running_driver_version`]. Versions compare by
+/// major then minor number
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub struct Version {
     pub major: u16,
@@ -134,6 +334,9 @@ impl std::fmt::Display for Version {
     }
 }
 
+/// Alias of [`Version`], e.g. `if version < DriverVersion { major: 0, minor: 14 } { ... }`
+pub type DriverVersion = Version;
+
 /// Returns the major and minor version of the wintun driver
 pub fn get_running_driver_version(wintun: &Wintun) -> Result<Version> {
     let version = unsafe { wintun.WintunGetRunningDriverVersion() };
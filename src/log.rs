@@ -1,5 +1,11 @@
 use crate::{util, wintun_raw, Wintun};
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::{
+    cell::RefCell,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
+};
 
 /// Sets the logger wintun will use when logging. Maps to the WintunSetLogger C function
 pub fn set_logger(wintun: &Wintun, f: wintun_raw::WINTUN_LOGGER_CALLBACK) {
@@ -10,14 +16,104 @@ pub fn reset_logger(wintun: &Wintun) {
     set_logger(wintun, None);
 }
 
+/// A logger closure installed with [`set_logger_closure`]. Receives the level, the timestamp in
+/// 100ns intervals since 1601-01-01 UTC and the message
+pub type LoggerClosure = dyn Fn(log::Level, u64, &str) + Send + Sync;
+
+static LOGGER_CLOSURE: RwLock<Option<Arc<LoggerClosure>>> = RwLock::new(None);
+
+/// Sets a Rust closure as the logger wintun will use when logging.
+///
+/// The closure is stored globally and called through a trampoline installed with
+/// [`set_logger`], so it may be invoked from any thread making a WinTun call. Calling this again
+/// replaces the closure; a call already in progress finishes with the closure it started with.
+pub fn set_logger_closure<F>(wintun: &Wintun, f: F)
+where
+    F: Fn(log::Level, u64, &str) + Send + Sync + 'static,
+{
+    let closure: Arc<LoggerClosure> = Arc::new(f);
+    match LOGGER_CLOSURE.write() {
+        Ok(mut slot) => *slot = Some(closure),
+        Err(poisoned) => *poisoned.into_inner() = Some(closure),
+    }
+    SET_LOGGER.store(true, Ordering::SeqCst);
+    set_logger(wintun, Some(closure_logger));
+}
+
+fn wintun_level(level: wintun_raw::WINTUN_LOGGER_LEVEL) -> log::Level {
+    match level {
+        wintun_raw::WINTUN_LOGGER_LEVEL_WINTUN_LOG_INFO => log::Level::Info,
+        wintun_raw::WINTUN_LOGGER_LEVEL_WINTUN_LOG_WARN => log::Level::Warn,
+        _ => log::Level::Error,
+    }
+}
+
+/// Trampoline forwarding WinTun messages to the closure set with [`set_logger_closure`]
+unsafe extern "stdcall" fn closure_logger(
+    level: wintun_raw::WINTUN_LOGGER_LEVEL,
+    timestamp: wintun_raw::DWORD64,
+    message: windows_sys::core::PCWSTR,
+) {
+    // Clone the closure out so the lock isn't held while it runs, letting it be replaced meanwhile
+    let closure = match LOGGER_CLOSURE.read() {
+        Ok(slot) => slot.clone(),
+        Err(poisoned) => poisoned.into_inner().clone(),
+    };
+    let msg = util::win_pwstr_to_string(message as *mut u16).unwrap_or_else(|e| e.to_string());
+    let level = wintun_level(level);
+    // Recorded like by `default_logger`, so failed WinTun calls still report the message
+    #[cfg(feature = "enable_inner_logging")]
+    record_log_item(LogItem::new(level, msg.clone(), timestamp));
+    if let Some(closure) = closure {
+        closure(level, timestamp, &msg);
+    }
+}
+
 static SET_LOGGER: AtomicBool = AtomicBool::new(false);
 
-#[allow(dead_code)]
+static LOG_LEVEL_FILTER: AtomicUsize = AtomicUsize::new(log::LevelFilter::Trace as usize);
+
+/// Sets the maximum level of WinTun messages that [`default_logger`] forwards to the `log` crate.
+///
+/// Messages above this level are not forwarded, but are still recorded internally so they can be
+/// reported when a WinTun call fails.
+pub fn set_log_level_filter(level: log::LevelFilter) {
+    LOG_LEVEL_FILTER.store(level as usize, Ordering::Relaxed);
+}
+
+fn log_level_enabled(level: log::Level) -> bool {
+    level as usize <= LOG_LEVEL_FILTER.load(Ordering::Relaxed)
+}
+
+thread_local! {
+    /// Name of the adapter the WinTun call in progress on this thread operates on
+    static LOG_CONTEXT: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Attributes WinTun messages logged on this thread to the adapter `name` until dropped
+pub(crate) struct LogContextGuard(Option<String>);
+
+/// WinTun's logger callback is global to the loaded dll, but it is invoked synchronously on the
+/// thread making the WinTun call. Wrapping each call in a context lets [`default_logger`] prefix the
+/// messages with the adapter they belong to.
+pub(crate) fn log_context(name: &str) -> LogContextGuard {
+    LogContextGuard(LOG_CONTEXT.with(|context| context.replace(Some(name.to_string()))))
+}
+
+impl Drop for LogContextGuard {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        LOG_CONTEXT.with(|context| *context.borrow_mut() = previous);
+    }
+}
+
+/// A message logged by WinTun, recorded when the `enable_inner_logging` feature is enabled
 #[derive(Debug, Clone)]
-pub(crate) struct LogItem {
-    pub(crate) level: log::Level,
-    pub(crate) msg: String,
-    pub(crate) timestamp: u64,
+pub struct LogItem {
+    pub level: log::Level,
+    pub msg: String,
+    /// Time of the message, in 100ns intervals since 1601-01-01 UTC
+    pub timestamp: u64,
 }
 
 impl LogItem {
@@ -31,6 +127,40 @@ impl LogItem {
 static LOG_CONTAINER: std::sync::LazyLock<std::sync::Mutex<std::collections::VecDeque<LogItem>>> =
     std::sync::LazyLock::new(|| std::sync::Mutex::new(std::collections::VecDeque::new()));
 
+/// Default maximum number of recorded WinTun messages, see [`set_log_capacity`]
+#[cfg(feature = "enable_inner_logging")]
+pub const DEFAULT_LOG_CAPACITY: usize = 256;
+
+#[cfg(feature = "enable_inner_logging")]
+static LOG_CAPACITY: AtomicUsize = AtomicUsize::new(DEFAULT_LOG_CAPACITY);
+
+/// Sets the maximum number of WinTun messages kept for error reporting. When full, the oldest
+/// messages are dropped to make room for new ones.
+#[cfg(feature = "enable_inner_logging")]
+pub fn set_log_capacity(capacity: usize) {
+    LOG_CAPACITY.store(capacity, Ordering::Relaxed);
+    if let Ok(mut log) = LOG_CONTAINER.lock() {
+        let excess = log.len().saturating_sub(capacity);
+        log.drain(..excess);
+    }
+}
+
+#[cfg(feature = "enable_inner_logging")]
+fn record_log_item(item: LogItem) {
+    if let Err(e) = LOG_CONTAINER.lock().map(|mut log| {
+        let capacity = LOG_CAPACITY.load(Ordering::Relaxed);
+        if capacity == 0 {
+            return;
+        }
+        while log.len() >= capacity {
+            log.pop_front();
+        }
+        log.push_back(item);
+    }) {
+        log::error!("Failed to log message: {}", e);
+    }
+}
+
 /// The logger that is active by default. Logs messages to the log crate
 ///
 /// # Safety
@@ -42,14 +172,22 @@ pub unsafe extern "stdcall" fn default_logger(
 ) {
     //Wintun will always give us a valid UTF16 null termineted string
     let utf8_msg = util::win_pwstr_to_string(message as *mut u16).unwrap_or_else(|e| e.to_string());
+    let source = LOG_CONTEXT.with(|context| match context.borrow().as_deref() {
+        Some(name) => format!("WinTun [{}]", name),
+        None => "WinTun".to_string(),
+    });
 
     let _l = match level {
         wintun_raw::WINTUN_LOGGER_LEVEL_WINTUN_LOG_INFO => {
-            log::info!("WinTun: {}", utf8_msg);
+            if log_level_enabled(log::Level::Info) {
+                log::info!("{}: {}", source, utf8_msg);
+            }
             log::Level::Info
         }
         wintun_raw::WINTUN_LOGGER_LEVEL_WINTUN_LOG_WARN => {
-            log::warn!("WinTun: {}", utf8_msg);
+            if log_level_enabled(log::Level::Warn) {
+                log::warn!("{}: {}", source, utf8_msg);
+            }
             log::Level::Warn
         }
         wintun_raw::WINTUN_LOGGER_LEVEL_WINTUN_LOG_ERR => log::Level::Error,
@@ -57,14 +195,10 @@ pub unsafe extern "stdcall" fn default_logger(
     };
 
     #[cfg(feature = "enable_inner_logging")]
-    if let Err(e) = LOG_CONTAINER.lock().map(|mut log| {
-        log.push_back(LogItem::new(_l, utf8_msg, _timestamp));
-    }) {
-        log::error!("Failed to log message: {}", e);
-    }
+    record_log_item(LogItem::new(_l, utf8_msg, _timestamp));
     #[cfg(not(feature = "enable_inner_logging"))]
-    if _l == log::Level::Error {
-        log::error!("WinTun: {}", utf8_msg);
+    if _l == log::Level::Error && log_level_enabled(_l) {
+        log::error!("{}: {}", source, utf8_msg);
     }
 }
 
@@ -76,6 +210,20 @@ fn get_log() -> Vec<LogItem> {
         .unwrap_or_else(|_e| Vec::new())
 }
 
+/// Removes and returns the recorded WinTun messages of level `min_level` or more severe, in the
+/// order they were logged. The less severe messages are kept.
+#[cfg(feature = "enable_inner_logging")]
+pub fn drain_logs_filtered(min_level: log::Level) -> Vec<LogItem> {
+    LOG_CONTAINER
+        .lock()
+        .map(|mut log| {
+            let (drained, kept) = log.drain(..).partition(|item| item.level <= min_level);
+            *log = kept;
+            drained.into()
+        })
+        .unwrap_or_else(|_e| Vec::new())
+}
+
 #[cfg(feature = "enable_inner_logging")]
 fn get_worst_log_msg(container: &[LogItem]) -> Option<&LogItem> {
     container.iter().max_by_key(|item| match item.level {
@@ -86,14 +234,26 @@ fn get_worst_log_msg(container: &[LogItem]) -> Option<&LogItem> {
     })
 }
 
-pub(crate) fn extract_wintun_log_error<T>(prifix: &str) -> Result<T, String> {
+/// Builds an [`Error::WintunDriver`](crate::Error::WintunDriver) for the failed WinTun call
+/// described by `context`, from the thread's last error code and the most severe recorded message
+pub(crate) fn extract_wintun_log_error<T>(context: &str) -> Result<T, crate::Error> {
+    // Read first, so nothing below can overwrite the error WinTun left behind
+    let code = match unsafe { windows_sys::Win32::Foundation::GetLastError() } {
+        0 => None,
+        code => Some(code),
+    };
     #[cfg(not(feature = "enable_inner_logging"))]
-    let info = "No inner logs".to_string();
+    let (level, message) = (log::Level::Error, "No inner logs".to_string());
     #[cfg(feature = "enable_inner_logging")]
-    let info = get_worst_log_msg(&get_log())
-        .map(|item| item.msg.clone())
-        .unwrap_or_else(|| "No inner logs".to_string());
-    Err(format!("{} \"{}\"", prifix, info))
+    let (level, message) = get_worst_log_msg(&get_log())
+        .map(|item| (item.level, item.msg.clone()))
+        .unwrap_or_else(|| (log::Level::Error, "No inner logs".to_string()));
+    Err(crate::Error::WintunDriver {
+        context: context.to_string(),
+        level,
+        message,
+        code,
+    })
 }
 
 pub(crate) fn set_default_logger_if_unset(wintun: &Wintun) {
@@ -104,3 +264,30 @@ pub(crate) fn set_default_logger_if_unset(wintun: &Wintun) {
         set_logger(wintun, Some(default_logger));
     }
 }
+
+/// Detaches the logger from `wintun`, so the default logger is installed again on the next load
+pub(crate) fn unset_logger(wintun: &Wintun) {
+    reset_logger(wintun);
+    match LOGGER_CLOSURE.write() {
+        Ok(mut slot) => *slot = None,
+        Err(poisoned) => *poisoned.into_inner() = None,
+    }
+    SET_LOGGER.store(false, Ordering::SeqCst);
+}
+
+#[cfg(all(test, feature = "enable_inner_logging"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_container_is_bounded() {
+        set_log_capacity(16);
+        for i in 0..10_000 {
+            record_log_item(LogItem::new(log::Level::Info, i.to_string(), i));
+        }
+        let log = get_log();
+        assert_eq!(log.len(), 16);
+        assert_eq!(log.first().map(|item| item.timestamp), Some(10_000 - 16));
+        set_log_capacity(DEFAULT_LOG_CAPACITY);
+    }
+}
@@ -0,0 +1,215 @@
+//! Minimal bindings to the Network List Manager COM API, which windows-sys has no interfaces for.
+//!
+//! Only the methods needed to read and change the category of an adapter's network are declared,
+//! the other vtable slots are kept as placeholders so the offsets match the Windows SDK headers.
+
+use crate::{util, Error, NetworkCategory};
+use std::ffi::c_void;
+use windows_sys::{
+    core::{GUID, HRESULT},
+    Win32::{
+        Foundation::{RPC_E_CHANGED_MODE, S_OK},
+        System::Com::{CoCreateInstance, CoInitializeEx, CoUninitialize, CLSCTX_ALL, COINIT_MULTITHREADED},
+    },
+};
+
+const CLSID_NETWORK_LIST_MANAGER: GUID = GUID::from_u128(0xdcb00c01_570f_4a9b_8d69_199fdba5723b);
+const IID_INETWORK_LIST_MANAGER: GUID = GUID::from_u128(0xdcb00000_570f_4a9b_8d69_199fdba5723b);
+
+const NLM_NETWORK_CATEGORY_PUBLIC: i32 = 0;
+const NLM_NETWORK_CATEGORY_PRIVATE: i32 = 1;
+const NLM_NETWORK_CATEGORY_DOMAIN_AUTHENTICATED: i32 = 2;
+
+type Slot = usize;
+
+/// `IDispatch`, the base of all the Network List Manager interfaces
+#[repr(C)]
+struct IDispatchVtbl {
+    _query_interface: Slot,
+    _add_ref: Slot,
+    release: unsafe extern "system" fn(*mut c_void) -> u32,
+    _get_type_info_count: Slot,
+    _get_type_info: Slot,
+    _get_ids_of_names: Slot,
+    _invoke: Slot,
+}
+
+#[repr(C)]
+struct INetworkListManagerVtbl {
+    base: IDispatchVtbl,
+    _get_networks: Slot,
+    _get_network: Slot,
+    get_network_connections: unsafe extern "system" fn(*mut c_void, *mut *mut c_void) -> HRESULT,
+}
+
+#[repr(C)]
+struct IEnumNetworkConnectionsVtbl {
+    base: IDispatchVtbl,
+    _new_enum: Slot,
+    next: unsafe extern "system" fn(*mut c_void, u32, *mut *mut c_void, *mut u32) -> HRESULT,
+}
+
+#[repr(C)]
+struct INetworkConnectionVtbl {
+    base: IDispatchVtbl,
+    get_network: unsafe extern "system" fn(*mut c_void, *mut *mut c_void) -> HRESULT,
+    _is_connected_to_internet: Slot,
+    _is_connected: Slot,
+    _get_connectivity: Slot,
+    _get_connection_id: Slot,
+    get_adapter_id: unsafe extern "system" fn(*mut c_void, *mut GUID) -> HRESULT,
+}
+
+#[repr(C)]
+struct INetworkVtbl {
+    base: IDispatchVtbl,
+    _get_name: Slot,
+    _set_name: Slot,
+    _get_description: Slot,
+    _set_description: Slot,
+    _get_network_id: Slot,
+    _get_domain_type: Slot,
+    _get_network_connections: Slot,
+    _get_time_created_and_connected: Slot,
+    _is_connected_to_internet: Slot,
+    _is_connected: Slot,
+    _get_connectivity: Slot,
+    get_category: unsafe extern "system" fn(*mut c_void, *mut i32) -> HRESULT,
+    set_category: unsafe extern "system" fn(*mut c_void, i32) -> HRESULT,
+}
+
+/// An owned COM interface pointer, released on drop
+struct ComPtr(*mut c_void);
+
+impl ComPtr {
+    /// # Safety
+    /// `V` must be the vtable layout of the interface this pointer was obtained as
+    unsafe fn vtbl<V>(&self) -> &V {
+        &**(self.0 as *mut *const V)
+    }
+}
+
+impl Drop for ComPtr {
+    fn drop(&mut self) {
+        unsafe { (self.vtbl::<IDispatchVtbl>().release)(self.0) };
+    }
+}
+
+/// Keeps COM initialized on this thread while alive
+struct ComGuard(bool);
+
+impl ComGuard {
+    fn new() -> Result<Self, Error> {
+        match unsafe { CoInitializeEx(std::ptr::null(), COINIT_MULTITHREADED as u32) } {
+            // Already initialized as single-threaded by the caller, which works as well
+            RPC_E_CHANGED_MODE => Ok(ComGuard(false)),
+            hr if hr >= 0 => Ok(ComGuard(true)),
+            hr => Err(Error::from_win32(hr as u32, "CoInitializeEx")),
+        }
+    }
+}
+
+impl Drop for ComGuard {
+    fn drop(&mut self) {
+        if self.0 {
+            unsafe { CoUninitialize() };
+        }
+    }
+}
+
+fn check(hr: HRESULT, context: &str) -> Result<(), Error> {
+    match hr {
+        hr if hr >= 0 => Ok(()),
+        hr => Err(Error::from_win32(hr as u32, context)),
+    }
+}
+
+/// Calls `f` with the `INetwork` the adapter `adapter_guid` is connected to
+fn with_adapter_network<R>(adapter_guid: u128, f: impl FnOnce(&ComPtr) -> Result<R, Error>) -> Result<R, Error> {
+    let _com = ComGuard::new()?;
+    let mut manager = std::ptr::null_mut();
+    let hr = unsafe {
+        CoCreateInstance(
+            &CLSID_NETWORK_LIST_MANAGER,
+            std::ptr::null_mut(),
+            CLSCTX_ALL,
+            &IID_INETWORK_LIST_MANAGER,
+            &mut manager,
+        )
+    };
+    check(hr, "CoCreateInstance(NetworkListManager)")?;
+    let manager = ComPtr(manager);
+
+    let mut connections = std::ptr::null_mut();
+    let vtbl = unsafe { manager.vtbl::<INetworkListManagerVtbl>() };
+    check(
+        unsafe { (vtbl.get_network_connections)(manager.0, &mut connections) },
+        "INetworkListManager::GetNetworkConnections",
+    )?;
+    let connections = ComPtr(connections);
+
+    loop {
+        let mut connection = std::ptr::null_mut();
+        let mut fetched = 0;
+        let vtbl = unsafe { connections.vtbl::<IEnumNetworkConnectionsVtbl>() };
+        let hr = unsafe { (vtbl.next)(connections.0, 1, &mut connection, &mut fetched) };
+        check(hr, "IEnumNetworkConnections::Next")?;
+        if hr != S_OK || fetched != 1 {
+            return Err("No network connection found for the adapter".into());
+        }
+        let connection = ComPtr(connection);
+
+        let vtbl = unsafe { connection.vtbl::<INetworkConnectionVtbl>() };
+        let mut adapter_id: GUID = unsafe { std::mem::zeroed() };
+        check(
+            unsafe { (vtbl.get_adapter_id)(connection.0, &mut adapter_id) },
+            "INetworkConnection::GetAdapterId",
+        )?;
+        if util::win_guid_to_u128(&adapter_id) != adapter_guid {
+            continue;
+        }
+
+        let mut network = std::ptr::null_mut();
+        check(
+            unsafe { (vtbl.get_network)(connection.0, &mut network) },
+            "INetworkConnection::GetNetwork",
+        )?;
+        return f(&ComPtr(network));
+    }
+}
+
+/// Returns the category of the network the adapter `adapter_guid` is connected to
+pub(crate) fn get_network_category(adapter_guid: u128) -> Result<NetworkCategory, Error> {
+    with_adapter_network(adapter_guid, |network| {
+        let mut category = 0;
+        let vtbl = unsafe { network.vtbl::<INetworkVtbl>() };
+        check(
+            unsafe { (vtbl.get_category)(network.0, &mut category) },
+            "INetwork::GetCategory",
+        )?;
+        match category {
+            NLM_NETWORK_CATEGORY_PUBLIC => Ok(NetworkCategory::Public),
+            NLM_NETWORK_CATEGORY_PRIVATE => Ok(NetworkCategory::Private),
+            NLM_NETWORK_CATEGORY_DOMAIN_AUTHENTICATED => Ok(NetworkCategory::DomainAuthenticated),
+            other => Err(format!("Unknown network category {}", other).into()),
+        }
+    })
+}
+
+/// Sets the category of the network the adapter `adapter_guid` is connected to
+pub(crate) fn set_network_category(adapter_guid: u128, category: NetworkCategory) -> Result<(), Error> {
+    let value = match category {
+        NetworkCategory::Public => NLM_NETWORK_CATEGORY_PUBLIC,
+        NetworkCategory::Private => NLM_NETWORK_CATEGORY_PRIVATE,
+        NetworkCategory::DomainAuthenticated => NLM_NETWORK_CATEGORY_DOMAIN_AUTHENTICATED,
+    };
+    with_adapter_network(adapter_guid, |network| {
+        let category_s = format!("category={}", category.as_str());
+        util::notify_command_observer("INetwork::SetCategory", &[&category_s]);
+        let vtbl = unsafe { network.vtbl::<INetworkVtbl>() };
+        check(
+            unsafe { (vtbl.set_category)(network.0, value) },
+            "INetwork::SetCategory",
+        )
+    })
+}
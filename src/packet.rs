@@ -1,5 +1,73 @@
 use crate::session::Session;
-use std::sync::Arc;
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    sync::{atomic::Ordering, Arc},
+};
+
+/// A send packet allocated this many packets before the one being sent, and still not sent, is
+/// reported by [`UnsentGuard`]
+#[cfg(debug_assertions)]
+const STALE_SEND_DISTANCE: u64 = 64;
+
+/// Debug build bookkeeping of the allocated but unsent packets of a session, reporting the misuse
+/// described in [`Session::allocate_send_packet`]
+#[cfg(debug_assertions)]
+#[derive(Default)]
+pub(crate) struct UnsentGuard {
+    next_seq: std::sync::atomic::AtomicU64,
+    outstanding: std::sync::Mutex<std::collections::BTreeSet<u64>>,
+}
+
+#[cfg(debug_assertions)]
+impl UnsentGuard {
+    /// Registers a newly allocated send packet, returning its sequence number
+    pub(crate) fn allocated(&self) -> u64 {
+        let seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        if let Ok(mut outstanding) = self.outstanding.lock() {
+            outstanding.insert(seq);
+        }
+        seq
+    }
+
+    pub(crate) fn sent(&self, seq: u64) {
+        let Ok(mut outstanding) = self.outstanding.lock() else {
+            return;
+        };
+        outstanding.remove(&seq);
+        if let Some(&oldest) = outstanding.first() {
+            // Packets allocated after this one don't hold it up
+            if seq
+                .checked_sub(oldest)
+                .is_some_and(|distance| distance >= STALE_SEND_DISTANCE)
+            {
+                log::warn!(
+                    "Send packet #{} is still unsent after packet #{} was sent, it holds up the send queue",
+                    oldest,
+                    seq
+                );
+            }
+        }
+    }
+
+    pub(crate) fn dropped(&self, seq: u64) {
+        if let Ok(mut outstanding) = self.outstanding.lock() {
+            outstanding.remove(&seq);
+        }
+        log::warn!(
+            "Send packet #{} was dropped without being sent, it holds up the send queue",
+            seq
+        );
+    }
+
+    /// Called when the session is dropped, packets can only be outstanding here if they were leaked
+    pub(crate) fn check_on_drop(&self) {
+        if let Ok(outstanding) = self.outstanding.lock() {
+            if !outstanding.is_empty() {
+                log::warn!("Session dropped with {} unsent send packets", outstanding.len());
+            }
+        }
+    }
+}
 
 pub(crate) enum Kind {
     SendPacketPending, //Send packet type, but not sent yet
@@ -39,6 +107,10 @@ pub struct Packet {
     /// Share ownership of session to prevent the session from being dropped before packets that
     /// belong to it
     pub(crate) session: Arc<Session>,
+
+    /// Allocation order of a send packet, checked by [`UnsentGuard`]
+    #[cfg(debug_assertions)]
+    pub(crate) seq: u64,
 }
 
 impl Packet {
@@ -53,6 +125,54 @@ impl Packet {
     pub fn bytes(&self) -> &[u8] {
         self.bytes
     }
+
+    /// Returns the IP version of this packet, 4 or 6, or `None` if it is not a well-formed IP
+    /// packet
+    pub fn ip_version(&self) -> Option<u8> {
+        ip_payload_range(self.bytes).map(|_| self.bytes[0] >> 4)
+    }
+
+    /// Returns the source and destination addresses of this packet, or `None` if it is not a
+    /// well-formed IP packet
+    pub fn addresses(&self) -> Option<(IpAddr, IpAddr)> {
+        let bytes = &*self.bytes;
+        match self.ip_version()? {
+            4 => {
+                let src: [u8; 4] = bytes[12..16].try_into().ok()?;
+                let dst: [u8; 4] = bytes[16..20].try_into().ok()?;
+                Some((Ipv4Addr::from(src).into(), Ipv4Addr::from(dst).into()))
+            }
+            _ => {
+                let src: [u8; 16] = bytes[8..24].try_into().ok()?;
+                let dst: [u8; 16] = bytes[24..40].try_into().ok()?;
+                Some((Ipv6Addr::from(src).into(), Ipv6Addr::from(dst).into()))
+            }
+        }
+    }
+
+    /// Returns the bytes following the IP header of this packet, up to the length given in the
+    /// header, or `None` if it is not a well-formed IP packet. IPv6 extension headers are part of
+    /// the payload.
+    pub fn payload(&self) -> Option<&[u8]> {
+        ip_payload_range(self.bytes).map(|range| &self.bytes[range])
+    }
+}
+
+/// Returns the range of the payload in the IP packet `bytes`, checking the header length (IHL)
+/// and total length of IPv4, and the fixed header and payload length of IPv6
+fn ip_payload_range(bytes: &[u8]) -> Option<std::ops::Range<usize>> {
+    match bytes.first()? >> 4 {
+        4 if bytes.len() >= 20 => {
+            let header_len = (bytes[0] & 0x0f) as usize * 4;
+            let total_len = u16::from_be_bytes([bytes[2], bytes[3]]) as usize;
+            (header_len >= 20 && header_len <= total_len && total_len <= bytes.len()).then_some(header_len..total_len)
+        }
+        6 if bytes.len() >= 40 => {
+            let total_len = 40 + u16::from_be_bytes([bytes[4], bytes[5]]) as usize;
+            (total_len <= bytes.len()).then_some(40..total_len)
+        }
+        _ => None,
+    }
 }
 
 impl Drop for Packet {
@@ -76,6 +196,10 @@ impl Drop for Packet {
                 //dropped without being sent, this will hold up the send queue because wintun expects
                 //that every allocated packet is sent
 
+                self.session.pending_sends.fetch_sub(1, Ordering::AcqRel);
+                #[cfg(debug_assertions)]
+                self.session.unsent_guard.dropped(self.seq);
+
                 #[cfg(feature = "panic_on_unsent_packets")]
                 panic!("Packet was never sent!");
             }
@@ -85,3 +209,36 @@ impl Drop for Packet {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn unsent_guard_accepts_out_of_order_sends() {
+        let guard = UnsentGuard::default();
+        let a = guard.allocated();
+        let b = guard.allocated();
+        guard.sent(a);
+        guard.sent(b);
+        assert!(guard.outstanding.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn ip_payload_is_located_from_header() {
+        let mut v4 = vec![0u8; 28];
+        v4[0] = 0x46;
+        v4[3] = 28;
+        assert_eq!(ip_payload_range(&v4), Some(24..28));
+        v4[3] = 29;
+        assert_eq!(ip_payload_range(&v4), None);
+
+        let mut v6 = vec![0u8; 48];
+        v6[0] = 0x60;
+        v6[5] = 8;
+        assert_eq!(ip_payload_range(&v6), Some(40..48));
+        assert_eq!(ip_payload_range(&v6[..39]), None);
+        assert_eq!(ip_payload_range(&[]), None);
+    }
+}
@@ -0,0 +1,95 @@
+use crate::Error;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// LINKTYPE_RAW, packets begin with an IPv4 or IPv6 header
+const LINKTYPE_RAW: u32 = 101;
+
+/// The pcap capture a session copies its packets into, shared between the session and its [`PcapGuard`]
+#[derive(Default)]
+pub(crate) struct PcapTee {
+    active: AtomicBool,
+    writer: Mutex<Option<BufWriter<File>>>,
+}
+
+impl PcapTee {
+    pub(crate) fn start(self: &Arc<Self>, path: &Path) -> Result<PcapGuard, Error> {
+        let mut current = self
+            .writer
+            .lock()
+            .map_err(|e| format!("Lock pcap writer failed: {}", e))?;
+        if current.is_some() {
+            return Err("A pcap capture is already active on this session".into());
+        }
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(&0xa1b2c3d4_u32.to_le_bytes())?;
+        writer.write_all(&2_u16.to_le_bytes())?;
+        writer.write_all(&4_u16.to_le_bytes())?;
+        writer.write_all(&0_i32.to_le_bytes())?;
+        writer.write_all(&0_u32.to_le_bytes())?;
+        writer.write_all(&crate::MAX_IP_PACKET_SIZE.to_le_bytes())?;
+        writer.write_all(&LINKTYPE_RAW.to_le_bytes())?;
+
+        *current = Some(writer);
+        self.active.store(true, Ordering::Release);
+        Ok(PcapGuard { tee: self.clone() })
+    }
+
+    /// Appends `bytes` as one packet record, does nothing unless a capture is active
+    #[inline]
+    pub(crate) fn write(&self, bytes: &[u8]) {
+        if !self.active.load(Ordering::Acquire) {
+            return;
+        }
+        let Ok(mut writer) = self.writer.lock() else {
+            return;
+        };
+        let Some(writer) = writer.as_mut() else {
+            return;
+        };
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let len = bytes.len() as u32;
+        let result = (|| {
+            writer.write_all(&(now.as_secs() as u32).to_le_bytes())?;
+            writer.write_all(&now.subsec_micros().to_le_bytes())?;
+            writer.write_all(&len.to_le_bytes())?;
+            writer.write_all(&len.to_le_bytes())?;
+            writer.write_all(bytes)
+        })();
+        if let Err(e) = result {
+            log::warn!("Failed to write packet to pcap file: {}", e);
+        }
+    }
+
+    fn stop(&self) {
+        self.active.store(false, Ordering::Release);
+        if let Ok(mut writer) = self.writer.lock() {
+            if let Some(mut writer) = writer.take() {
+                if let Err(e) = writer.flush() {
+                    log::warn!("Failed to flush pcap file: {}", e);
+                }
+            }
+        }
+    }
+}
+
+/// Keeps a pcap capture started by [`crate::Session::tee_to_pcap`] running, the file is flushed
+/// and closed when the guard is dropped
+pub struct PcapGuard {
+    tee: Arc<PcapTee>,
+}
+
+impl Drop for PcapGuard {
+    fn drop(&mut self) {
+        self.tee.stop();
+    }
+}
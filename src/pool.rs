@@ -0,0 +1,151 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+/// The free buffers of a pool, each with its index in the pool
+type FreeSlots = Arc<Mutex<VecDeque<(usize, Vec<u8>)>>>;
+
+/// A fixed number of reusable packet buffers, filled by [`crate::Session::recv_into_pool`].
+///
+/// Buffers are handed out as [`PoolRef`]s and return to the pool when dropped, so reading packets
+/// doesn't allocate once the pool has been created.
+pub struct PacketPool {
+    free: FreeSlots,
+    slot_size: usize,
+}
+
+impl PacketPool {
+    /// Creates a pool of `slots` buffers, each `slot_size` bytes long.
+    ///
+    /// Use [`crate::MAX_IP_PACKET_SIZE`] as `slot_size` to be able to hold any packet.
+    pub fn new(slots: usize, slot_size: usize) -> Self {
+        let free = (0..slots).map(|index| (index, vec![0; slot_size])).collect();
+        Self {
+            free: Arc::new(Mutex::new(free)),
+            slot_size,
+        }
+    }
+
+    /// Creates a pool from caller-provided buffers, e.g. carved from shared memory, which are
+    /// reused for every packet read with [`crate::Session::recv_into_pool`].
+    ///
+    /// The slot size is the length of the shortest buffer, so any packet up to that size fits in
+    /// whichever buffer it is read into. [`PoolRef::index`] tells which of `buffers` holds a packet.
+    pub fn from_buffers(buffers: Vec<Vec<u8>>) -> Self {
+        let slot_size = buffers.iter().map(Vec::len).min().unwrap_or(0);
+        Self {
+            free: Arc::new(Mutex::new(buffers.into_iter().enumerate().collect())),
+            slot_size,
+        }
+    }
+
+    /// Returns the size in bytes of each buffer in this pool
+    pub fn slot_size(&self) -> usize {
+        self.slot_size
+    }
+
+    /// Returns the number of buffers not currently handed out
+    pub fn available(&self) -> usize {
+        self.free.lock().map(|free| free.len()).unwrap_or(0)
+    }
+
+    pub(crate) fn take(&mut self) -> std::io::Result<PoolRef> {
+        let buf = self
+            .free
+            .lock()
+            .map_err(|e| std::io::Error::other(format!("Lock packet pool failed: {}", e)))?
+            .pop_front();
+        let (index, buf) =
+            buf.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::OutOfMemory, "No free slot in pool"))?;
+        Ok(PoolRef {
+            index,
+            buf,
+            len: 0,
+            free: self.free.clone(),
+        })
+    }
+}
+
+/// A buffer of a [`PacketPool`] holding a received packet. Returns to its pool on drop
+pub struct PoolRef {
+    index: usize,
+    buf: Vec<u8>,
+    len: usize,
+    free: FreeSlots,
+}
+
+impl PoolRef {
+    /// Returns the index of this buffer in its pool, which for a pool created with
+    /// [`PacketPool::from_buffers`] is its position in the registered buffers
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub(crate) fn buffer_mut(&mut self) -> &mut [u8] {
+        &mut self.buf
+    }
+
+    pub(crate) fn set_len(&mut self, len: usize) {
+        self.len = len;
+    }
+}
+
+impl std::ops::Deref for PoolRef {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.buf[..self.len]
+    }
+}
+
+impl std::ops::DerefMut for PoolRef {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.buf[..self.len]
+    }
+}
+
+impl Drop for PoolRef {
+    fn drop(&mut self) {
+        let buf = std::mem::take(&mut self.buf);
+        if let Ok(mut free) = self.free.lock() {
+            free.push_back((self.index, buf));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slots_return_to_pool_on_drop() {
+        let mut pool = PacketPool::new(2, 16);
+        let mut a = pool.take().unwrap();
+        a.set_len(4);
+        assert_eq!(a.len(), 4);
+        let _b = pool.take().unwrap();
+        assert_eq!(pool.available(), 0);
+        assert!(pool.take().is_err());
+        drop(a);
+        assert_eq!(pool.available(), 1);
+        assert_eq!(pool.take().unwrap().buffer_mut().len(), 16);
+    }
+
+    #[test]
+    fn caller_buffers_are_reused() {
+        let mut pool = PacketPool::from_buffers(vec![vec![0; 32], vec![0; 64]]);
+        assert_eq!(pool.slot_size(), 32);
+        assert_eq!(pool.available(), 2);
+        let a = pool.take().unwrap();
+        let mut b = pool.take().unwrap();
+        assert_eq!((a.index(), b.index()), (0, 1));
+        assert_eq!(b.buffer_mut().len(), 64);
+        assert!(pool.take().is_err());
+        drop(b);
+        drop(a);
+        assert_eq!(pool.available(), 2);
+        // Released buffers keep their index when handed out again
+        assert_eq!(pool.take().unwrap().index(), 1);
+    }
+}
@@ -0,0 +1,173 @@
+use crate::{util, Error};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use windows_sys::Win32::{
+    Foundation::{ERROR_NOT_FOUND, NO_ERROR},
+    NetworkManagement::{
+        IpHelper::{
+            CreateIpForwardEntry2, DeleteIpForwardEntry2, FreeMibTable, GetIpForwardTable2, InitializeIpForwardEntry,
+            MIB_IPFORWARD_ROW2, MIB_IPFORWARD_TABLE2,
+        },
+        Ndis::NET_LUID_LH,
+    },
+    Networking::WinSock::{AF_INET6, AF_UNSPEC},
+};
+
+/// A single entry of the system routing table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RouteEntry {
+    /// The destination network address of this route
+    pub destination: IpAddr,
+    /// The prefix length of the destination network, e.g. `24` for `255.255.255.0`
+    pub prefix_length: u8,
+    /// The next hop (gateway) of this route, unspecified address for on-link routes
+    pub next_hop: IpAddr,
+    /// The route metric, which is added to the interface metric to get the effective metric
+    pub metric: u32,
+    /// The route metric plus the metric of its interface, which is what Windows compares to pick a route.
+    /// Falls back to the route metric alone if the interface could not be queried.
+    pub effective_metric: u32,
+    /// The Win32 interface index this route is bound to
+    pub interface_index: u32,
+}
+
+impl RouteEntry {
+    pub(crate) fn from_row(row: &MIB_IPFORWARD_ROW2) -> std::io::Result<Self> {
+        let is_ipv6 = unsafe { row.DestinationPrefix.Prefix.si_family } == AF_INET6;
+        let interface_metric =
+            util::get_ip_interface_entry(&row.InterfaceLuid, is_ipv6).map_or(0, |entry| entry.Metric);
+        Ok(Self {
+            destination: unsafe { util::sockaddr_inet_to_ip_addr(&row.DestinationPrefix.Prefix)? },
+            prefix_length: row.DestinationPrefix.PrefixLength,
+            next_hop: unsafe { util::sockaddr_inet_to_ip_addr(&row.NextHop)? },
+            metric: row.Metric,
+            effective_metric: row.Metric.saturating_add(interface_metric),
+            interface_index: row.InterfaceIndex,
+        })
+    }
+}
+
+/// Walks the IPv4 and IPv6 routing tables, calling `callback` for each row until it returns `false`
+pub(crate) fn get_ip_forward_table<F>(mut callback: F) -> Result<(), Error>
+where
+    F: FnMut(&MIB_IPFORWARD_ROW2) -> bool,
+{
+    let mut table: *mut MIB_IPFORWARD_TABLE2 = std::ptr::null_mut();
+    unsafe {
+        match GetIpForwardTable2(AF_UNSPEC, &mut table as _) {
+            NO_ERROR => {}
+            e => return Err(Error::from_win32(e, "GetIpForwardTable2")),
+        }
+        if table.is_null() {
+            return Err(std::io::Error::from(std::io::ErrorKind::NotFound).into());
+        }
+        use std::slice::from_raw_parts;
+        let rows = from_raw_parts::<MIB_IPFORWARD_ROW2>(&(*table).Table[0], (*table).NumEntries as usize);
+        for row in rows {
+            if !callback(row) {
+                break;
+            }
+        }
+        FreeMibTable(table as _);
+    }
+    Ok(())
+}
+
+/// Returns all routes bound to the interface with the given index
+pub(crate) fn get_routes_by_index(index: u32) -> std::io::Result<Vec<RouteEntry>> {
+    let mut routes = vec![];
+    let mut error = None;
+    get_ip_forward_table(|row| {
+        if row.InterfaceIndex != index {
+            return true;
+        }
+        match RouteEntry::from_row(row) {
+            Ok(route) => routes.push(route),
+            Err(e) => {
+                error = Some(e);
+                return false;
+            }
+        }
+        true
+    })?;
+    match error {
+        Some(e) => Err(e),
+        None => Ok(routes),
+    }
+}
+
+/// Creates a route to `destination/prefix_length` on the interface `luid`.
+/// If `next_hop` is `None` the route is on-link.
+pub(crate) fn add_route(
+    luid: &NET_LUID_LH,
+    destination: IpAddr,
+    prefix_length: u8,
+    next_hop: Option<IpAddr>,
+    metric: u32,
+) -> Result<(), Error> {
+    let next_hop = next_hop.unwrap_or(match destination {
+        IpAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+        IpAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+    });
+    if destination.is_ipv4() != next_hop.is_ipv4() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Destination and next hop address families differ",
+        )
+        .into());
+    }
+
+    let mut row: MIB_IPFORWARD_ROW2 = unsafe { std::mem::zeroed() };
+    unsafe { InitializeIpForwardEntry(&mut row) };
+    row.InterfaceLuid = *luid;
+    row.DestinationPrefix.Prefix = util::ip_addr_to_sockaddr_inet(&destination);
+    row.DestinationPrefix.PrefixLength = prefix_length;
+    row.NextHop = util::ip_addr_to_sockaddr_inet(&next_hop);
+    row.Metric = metric;
+
+    let destination_s = format!("destination={}/{}", destination, prefix_length);
+    let next_hop_s = format!("next_hop={}", next_hop);
+    let metric_s = format!("metric={}", metric);
+    util::notify_command_observer("CreateIpForwardEntry2", &[&destination_s, &next_hop_s, &metric_s]);
+
+    match unsafe { CreateIpForwardEntry2(&row) } {
+        NO_ERROR => Ok(()),
+        e => Err(Error::from_win32(e, "CreateIpForwardEntry2")),
+    }
+}
+
+/// Deletes every route to `destination/prefix_length` on the interface `luid`, whatever its next hop.
+/// Fails with `ERROR_NOT_FOUND` if there is none.
+pub(crate) fn delete_routes(luid: &NET_LUID_LH, destination: IpAddr, prefix_length: u8) -> Result<(), Error> {
+    let mut rows = vec![];
+    let mut error = None;
+    get_ip_forward_table(|row| {
+        if unsafe { row.InterfaceLuid.Value != luid.Value } || row.DestinationPrefix.PrefixLength != prefix_length {
+            return true;
+        }
+        match unsafe { util::sockaddr_inet_to_ip_addr(&row.DestinationPrefix.Prefix) } {
+            Ok(addr) if addr == destination => rows.push(*row),
+            Ok(_) => {}
+            Err(e) => {
+                error = Some(e);
+                return false;
+            }
+        }
+        true
+    })?;
+    if let Some(e) = error {
+        return Err(e.into());
+    }
+    if rows.is_empty() {
+        return Err(Error::from_win32(ERROR_NOT_FOUND, "DeleteIpForwardEntry2"));
+    }
+
+    let destination_s = format!("destination={}/{}", destination, prefix_length);
+    for row in rows {
+        util::notify_command_observer("DeleteIpForwardEntry2", &[&destination_s]);
+        match unsafe { DeleteIpForwardEntry2(&row) } {
+            NO_ERROR => {}
+            e => return Err(Error::from_win32(e, "DeleteIpForwardEntry2")),
+        }
+    }
+    Ok(())
+}
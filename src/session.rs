@@ -1,14 +1,32 @@
 use crate::{
     handle::{SafeEvent, UnsafeHandle},
-    packet, util, wintun_raw, Adapter, Error, Wintun,
+    packet,
+    pool::{PacketPool, PoolRef},
+    util, wintun_raw, Adapter, Error, Wintun,
+};
+use std::{
+    ptr, slice,
+    sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+    sync::Arc,
+    sync::OnceLock,
 };
-use std::{ptr, slice, sync::Arc, sync::OnceLock};
 use windows_sys::Win32::{
-    Foundation::{GetLastError, ERROR_NO_MORE_ITEMS, FALSE, HANDLE, WAIT_EVENT, WAIT_FAILED, WAIT_OBJECT_0},
+    Foundation::{
+        GetLastError, ERROR_BUFFER_OVERFLOW, ERROR_NO_MORE_ITEMS, FALSE, HANDLE, NO_ERROR, WAIT_EVENT, WAIT_FAILED,
+        WAIT_OBJECT_0, WAIT_TIMEOUT,
+    },
     System::Threading::{WaitForMultipleObjects, INFINITE},
 };
 
+/// Number of receive attempts the blocking receive functions make before waiting on the read event
+pub(crate) const DEFAULT_SPIN_COUNT: u32 = 5;
+
 /// Wrapper around a <https://git.zx2c4.com/wintun/about/#wintun_session_handle>
+///
+/// WinTun's receive and send functions are thread safe, so a session shared through an `Arc` can
+/// be read from several threads at once: each packet is received by exactly one of them, but the
+/// threads may see the packets of a flow out of order. [`Session::into_multi_reader`] wraps this
+/// pattern. Packets are sent in the order they are allocated, whatever the thread.
 pub struct Session {
     /// The session handle given to us by WintunStartSession
     pub(crate) inner: UnsafeHandle<wintun_raw::WINTUN_SESSION_HANDLE>,
@@ -23,17 +41,99 @@ pub struct Session {
 
     /// The adapter that owns this session
     pub(crate) adapter: Arc<Adapter>,
+
+    /// The ring buffer size the session was started with
+    pub(crate) capacity: u32,
+
+    /// Number of packets allocated with [`Session::allocate_send_packet`] that have not been sent
+    /// or dropped yet, awaited by [`Session::shutdown_graceful`]
+    pub(crate) pending_sends: AtomicUsize,
+
+    /// Win32 error code of the most recent failed WinTun call, `0` if none failed yet
+    pub(crate) last_error_code: AtomicU32,
+
+    /// Number of receive attempts made before waiting on the read event, see [`Session::set_spin_count`]
+    pub(crate) spin_count: AtomicU32,
+
+    /// Set when a send packet could not be allocated because the send ring was full
+    pub(crate) send_ring_full: AtomicBool,
+
+    /// Set by [`Session::shutdown_graceful`] so the blocking receive functions drain the queue
+    pub(crate) drain_on_shutdown: AtomicBool,
+
+    /// Reports send packets that hold up the send queue, in debug builds only
+    #[cfg(debug_assertions)]
+    pub(crate) unsent_guard: packet::UnsentGuard,
+
+    /// The pcap capture started by [`Session::tee_to_pcap`]
+    #[cfg(feature = "pcap")]
+    pub(crate) pcap_tee: Arc<crate::pcap::PcapTee>,
 }
 
 impl Session {
+    /// Returns the ring buffer size this session was started with, see [`Adapter::start_session`]
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
     pub fn get_adapter(&self) -> Arc<Adapter> {
         self.adapter.clone()
     }
 
+    /// Returns the raw WinTun session handle, for calling WinTun functions not wrapped by this crate.
+    ///
+    /// The handle is only valid for as long as this session is alive, it must not be used after the
+    /// session is dropped and must never be passed to `WintunEndSession`, which happens on drop.
+    pub fn raw_handle(&self) -> wintun_raw::WINTUN_SESSION_HANDLE {
+        self.inner.0
+    }
+
     pub(crate) fn get_wintun(&self) -> Wintun {
         self.adapter.wintun.clone()
     }
 
+    /// Returns the Win32 error code and message of the most recent WinTun call of this session that
+    /// failed, if any. An empty receive queue is not considered a failure.
+    pub fn last_os_error(&self) -> Option<(u32, String)> {
+        let code = self.last_error_code.load(Ordering::Relaxed);
+        if code == NO_ERROR {
+            return None;
+        }
+        let message = util::format_message(code).map(|msg| msg.trim().to_string());
+        Some((code, message.unwrap_or_default()))
+    }
+
+    /// Returns `GetLastError()`, recording it for [`Session::last_os_error`]
+    fn take_last_error(&self) -> u32 {
+        let code = unsafe { GetLastError() };
+        if code != NO_ERROR && code != ERROR_NO_MORE_ITEMS {
+            self.last_error_code.store(code, Ordering::Relaxed);
+        }
+        if code == ERROR_BUFFER_OVERFLOW {
+            self.send_ring_full.store(true, Ordering::Release);
+        }
+        code
+    }
+
+    /// Like [`util::get_last_error`], recording the error for [`Session::last_os_error`]
+    fn get_last_error(&self) -> std::io::Result<String> {
+        util::get_os_error_from_id(self.take_last_error() as i32)?;
+        Ok("No error".to_string())
+    }
+
+    /// Starts writing a copy of every packet sent or received through this session into a pcap
+    /// file at `path`, until the returned guard is dropped
+    #[cfg(feature = "pcap")]
+    pub fn tee_to_pcap(&self, path: &std::path::Path) -> Result<crate::pcap::PcapGuard, Error> {
+        self.pcap_tee.start(path)
+    }
+
+    #[inline]
+    fn tee(&self, _bytes: &[u8]) {
+        #[cfg(feature = "pcap")]
+        self.pcap_tee.write(_bytes);
+    }
+
     /// Allocates a send packet of the specified size. Wraps WintunAllocateSendPacket
     ///
     /// All packets returned from this function must be sent using [`Session::send_packet`] because
@@ -45,14 +145,17 @@ impl Session {
         let wintun = self.get_wintun();
         let ptr = unsafe { wintun.WintunAllocateSendPacket(self.inner.0, size as u32) };
         if ptr.is_null() {
-            return Err(util::get_last_error()?.into());
+            return Err(self.get_last_error()?.into());
         }
+        self.pending_sends.fetch_add(1, Ordering::AcqRel);
         Ok(packet::Packet {
             //SAFETY: ptr is non null, aligned for u8, and readable for up to size bytes (which
             //must be less than isize::MAX because bytes is a u16
             bytes: unsafe { slice::from_raw_parts_mut(ptr, size as usize) },
             session: self.clone(),
             kind: packet::Kind::SendPacketPending,
+            #[cfg(debug_assertions)]
+            seq: self.unsent_guard.allocated(),
         })
     }
 
@@ -60,10 +163,14 @@ impl Session {
     pub fn send_packet(&self, mut packet: packet::Packet) {
         assert!(matches!(packet.kind, packet::Kind::SendPacketPending));
 
+        self.tee(packet.bytes);
         let wintun = self.get_wintun();
         unsafe { wintun.WintunSendPacket(self.inner.0, packet.bytes.as_ptr()) };
         //Mark the packet at sent
         packet.kind = packet::Kind::SendPacketSent;
+        self.pending_sends.fetch_sub(1, Ordering::AcqRel);
+        #[cfg(debug_assertions)]
+        self.unsent_guard.sent(packet.seq);
     }
 
     /// Attempts to receive a packet from the virtual interface without blocking.
@@ -78,18 +185,81 @@ impl Session {
         debug_assert!(size <= u16::MAX as u32);
         if ptr.is_null() {
             //Wintun returns ERROR_NO_MORE_ITEMS instead of blocking if packets are not available
-            return match unsafe { GetLastError() } {
+            return match self.take_last_error() {
                 ERROR_NO_MORE_ITEMS => Ok(None),
                 e => Err(std::io::Error::from_raw_os_error(e as i32).into()),
             };
         }
-        Ok(Some(packet::Packet {
+        let packet = packet::Packet {
             kind: packet::Kind::ReceivePacket,
             //SAFETY: ptr is non null, aligned for u8, and readable for up to size bytes (which
             //must be less than isize::MAX because bytes is a u16
             bytes: unsafe { slice::from_raw_parts_mut(ptr, size as usize) },
             session: self.clone(),
-        }))
+            #[cfg(debug_assertions)]
+            seq: 0,
+        };
+        self.tee(packet.bytes);
+        Ok(Some(packet))
+    }
+
+    /// Receives the next packet without blocking and passes its bytes to `f`, without copying.
+    ///
+    /// The packet's ring slot is released as soon as `f` returns (or panics), so the bytes can
+    /// never be held beyond the call. Returns `Ok(None)` if the receive queue is empty.
+    pub fn with_next_packet<R>(&self, f: impl FnOnce(&[u8]) -> R) -> Result<Option<R>, Error> {
+        Ok(self.try_peek_bytes()?.map(|bytes| f(&bytes)))
+    }
+
+    /// Receives the next packet without blocking, borrowing its bytes from the receive ring until
+    /// the returned [`PeekedBytes`] is dropped. Returns `Ok(None)` if the receive queue is empty.
+    ///
+    /// Unlike [`Session::try_receive`] this doesn't clone the session's `Arc` for each packet, for
+    /// the hottest read loops. The bytes and the release of their ring slot are one value, so the
+    /// bytes can't be used after the slot was released.
+    pub fn try_peek_bytes(&self) -> Result<Option<PeekedBytes<'_>>, Error> {
+        let mut size = 0u32;
+        let ptr = unsafe {
+            self.adapter
+                .wintun
+                .WintunReceivePacket(self.inner.0, &mut size as *mut u32)
+        };
+        if ptr.is_null() {
+            return match self.take_last_error() {
+                ERROR_NO_MORE_ITEMS => Ok(None),
+                e => Err(std::io::Error::from_raw_os_error(e as i32).into()),
+            };
+        }
+        //SAFETY: ptr is non null, aligned for u8, and readable for up to size bytes until the
+        //PeekedBytes releases it, which the borrow can't outlive
+        let bytes = unsafe { slice::from_raw_parts(ptr, size as usize) };
+        self.tee(bytes);
+        Ok(Some(PeekedBytes { session: self, bytes }))
+    }
+
+    /// Drains all packets currently queued in the receive ring into owned buffers, releasing each
+    /// ring slot right after it is copied.
+    ///
+    /// This function never blocks waiting for new packets, it returns an empty `Vec` if the
+    /// receive queue is empty.
+    pub fn recv_all_available(&self) -> Result<Vec<Vec<u8>>, Error> {
+        let wintun = &self.adapter.wintun;
+        let mut packets = vec![];
+        loop {
+            let mut size = 0u32;
+            let ptr = unsafe { wintun.WintunReceivePacket(self.inner.0, &mut size as *mut u32) };
+            if ptr.is_null() {
+                return match self.take_last_error() {
+                    ERROR_NO_MORE_ITEMS => Ok(packets),
+                    e => Err(std::io::Error::from_raw_os_error(e as i32).into()),
+                };
+            }
+            //SAFETY: ptr is non null, aligned for u8, and readable for up to size bytes
+            let bytes = unsafe { slice::from_raw_parts(ptr, size as usize) };
+            self.tee(bytes);
+            packets.push(bytes.to_vec());
+            unsafe { wintun.WintunReleaseReceivePacket(self.inner.0, ptr) };
+        }
     }
 
     /// # Safety
@@ -102,6 +272,16 @@ impl Session {
             .get_or_init(|| UnsafeHandle(unsafe { wintun.WintunGetReadWaitEvent(self.inner.0) })))
     }
 
+    /// Returns the event that is signaled when more data becomes available to read, for waiting on
+    /// it together with other handles, e.g. the read events of several sessions in one
+    /// `WaitForMultipleObjects` call.
+    ///
+    /// The handle is owned by the session and stays valid until the session is dropped. It must
+    /// not be closed by the caller.
+    pub fn read_event_handle(&self) -> Result<std::os::windows::io::RawHandle, Error> {
+        Ok(self.get_read_wait_event()?.0)
+    }
+
     pub fn get_shutdown_event(&self) -> UnsafeHandle<HANDLE> {
         self.shutdown_event.get_handle()
     }
@@ -110,10 +290,12 @@ impl Session {
     /// If the session is closed via [`Session::shutdown`] all threads currently blocking inside this function
     /// will return Err(())
     pub fn receive_blocking(self: &Arc<Self>) -> Result<packet::Packet, Error> {
+        let mut drained = false;
+        let mut attempts = self.get_spin_count();
         loop {
-            //Try 5 times to receive without blocking so we don't have to issue a syscall to wait
-            //for the event if packets are being received at a rapid rate
-            for _ in 0..5 {
+            //Try several times to receive without blocking so we don't have to issue a syscall to
+            //wait for the event if packets are being received at a rapid rate
+            for _ in 0..attempts {
                 match self.try_receive() {
                     Err(err) => return Err(err),
                     Ok(Some(packet)) => return Ok(packet),
@@ -123,29 +305,183 @@ impl Session {
                     }
                 }
             }
-            self.wait_read()?;
+            self.wait_read_or_drain(&mut drained)?;
+            //Once the event is signaled there is a packet, so try at least once
+            attempts = attempts.max(1);
+        }
+    }
+
+    /// Like [`Session::receive_blocking`], but gives up after `timeout`, returning `Ok(None)` so
+    /// a reader thread can wake up periodically. Returns [`Error::ShuttingDown`] once the session
+    /// is shut down.
+    pub fn receive_timeout(self: &Arc<Self>, timeout: std::time::Duration) -> Result<Option<packet::Packet>, Error> {
+        let deadline = std::time::Instant::now() + timeout;
+        // Try at least once, so a zero timeout still polls the queue
+        let attempts = self.get_spin_count().max(1);
+        loop {
+            for _ in 0..attempts {
+                if let Some(packet) = self.try_receive()? {
+                    return Ok(Some(packet));
+                }
+            }
+            let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+            // Round up, so a wait shorter than a millisecond doesn't become a busy loop
+            let millis = remaining.as_nanos().div_ceil(1_000_000);
+            if millis == 0 || !self.wait_read_timeout(u32::try_from(millis).unwrap_or(INFINITE - 1))? {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Sets how many times the blocking receive functions try to receive a packet before waiting
+    /// on the read event, 5 by default.
+    ///
+    /// A higher value trades CPU time for latency and fewer wait syscalls, 0 waits right away.
+    pub fn set_spin_count(&self, count: u32) {
+        self.spin_count.store(count, Ordering::Relaxed);
+    }
+
+    /// Returns the value set by [`Session::set_spin_count`]
+    pub fn get_spin_count(&self) -> u32 {
+        self.spin_count.load(Ordering::Relaxed)
+    }
+
+    /// Blocks until at least one packet is available, then appends up to `max` queued packets to
+    /// `out` without waiting again, returning how many were appended.
+    ///
+    /// Unlike calling [`Session::receive_blocking`] in a loop, this waits on the read event only
+    /// when the receive queue is empty, so a saturated ring is drained without a wait per packet.
+    /// Returns `Ok(0)` without blocking if `max` is 0.
+    pub fn receive_many(self: &Arc<Self>, out: &mut Vec<packet::Packet>, max: usize) -> Result<usize, Error> {
+        if max == 0 {
+            return Ok(0);
+        }
+        let mut drained = false;
+        loop {
+            let mut count = 0;
+            while count < max {
+                match self.try_receive()? {
+                    Some(packet) => {
+                        out.push(packet);
+                        count += 1;
+                    }
+                    None => break,
+                }
+            }
+            if count > 0 {
+                return Ok(count);
+            }
+            self.wait_read_or_drain(&mut drained)?;
+        }
+    }
+
+    /// Spawns `n` threads receiving packets from this session, and returns a [`MultiReader`]
+    /// collecting them into one channel.
+    ///
+    /// Each packet is copied out of the ring by the thread that received it, so the order between
+    /// packets received by different threads is not preserved. The threads exit when the session
+    /// is shut down or the [`MultiReader`] is dropped.
+    pub fn into_multi_reader(self: Arc<Self>, n: usize) -> Result<MultiReader, Error> {
+        if n == 0 {
+            return Err("At least one reader thread is required".into());
+        }
+        let (sender, receiver) = std::sync::mpsc::sync_channel(MULTI_READER_CHANNEL_CAPACITY);
+        let mut readers = Vec::with_capacity(n);
+        for i in 0..n {
+            let session = self.clone();
+            let sender = sender.clone();
+            let reader = std::thread::Builder::new()
+                .name(format!("wintun-reader-{}", i))
+                .spawn(move || loop {
+                    let bytes = match session.receive_blocking() {
+                        Ok(packet) => packet.bytes().to_vec(),
+                        Err(Error::ShuttingDown) => return Ok(()),
+                        Err(e) => return Err(e),
+                    };
+                    if sender.send(bytes).is_err() {
+                        // The MultiReader was dropped
+                        return Ok(());
+                    }
+                });
+            match reader {
+                Ok(reader) => readers.push(reader),
+                Err(e) => {
+                    self.shutdown()?;
+                    return Err(e.into());
+                }
+            }
+        }
+        Ok(MultiReader {
+            session: self,
+            receiver,
+            readers,
+        })
+    }
+
+    /// Returns the number of packets discarded on the adapter of this session, the sum of the
+    /// `InDiscards` and `OutDiscards` counters of its interface, e.g. when the rings overflow.
+    ///
+    /// The counters are interface-wide and cumulative since the interface was created, so they
+    /// also cover earlier sessions, compare two readings to detect new drops.
+    pub fn dropped_packets(&self) -> Result<u64, Error> {
+        let row = util::get_if_row2(&self.adapter.get_luid())?;
+        Ok(row.InDiscards + row.OutDiscards)
+    }
+
+    /// Returns an iterator calling [`Session::receive_blocking`] for each packet, which ends when
+    /// the session is shut down. The iterator keeps the session alive.
+    pub fn iter_blocking(self: &Arc<Self>) -> PacketIter {
+        PacketIter {
+            session: Some(self.clone()),
+        }
+    }
+
+    /// Splits the session into a receiving and a sending half, to be owned by different threads or
+    /// tasks. Neither half can shut the session down, keep another reference to the session for
+    /// that.
+    pub fn split(self: Arc<Self>) -> (PacketReader, PacketWriter) {
+        (PacketReader(self.clone()), PacketWriter(self))
+    }
+
+    /// Like [`Session::wait_read`], but after [`Session::shutdown_graceful`] returns `Ok` once more
+    /// instead of [`Error::ShuttingDown`], so the caller drains the packets still queued. `drained`
+    /// records that this happened, so the caller stops once the queue is empty.
+    fn wait_read_or_drain(&self, drained: &mut bool) -> Result<(), Error> {
+        match self.wait_read() {
+            Err(Error::ShuttingDown) if !*drained && self.drain_on_shutdown.load(Ordering::Acquire) => {
+                *drained = true;
+                Ok(())
+            }
+            result => result,
         }
     }
 
     pub fn wait_read(&self) -> Result<(), Error> {
+        self.wait_read_timeout(INFINITE).map(|_| ())
+    }
+
+    /// Waits up to `timeout` milliseconds for data to become available to read.
+    /// Returns `Ok(false)` if the timeout elapsed first
+    pub(crate) fn wait_read_timeout(&self, timeout: u32) -> Result<bool, Error> {
         //Wait on both the read handle and the shutdown handle so that we stop when requested
         let handles = [self.get_read_wait_event()?.0, self.shutdown_event.0 .0];
         let result = unsafe {
             //SAFETY: We abide by the requirements of WaitForMultipleObjects, handles is a
             //pointer to valid, aligned, stack memory
-            WaitForMultipleObjects(handles.len() as u32, &handles as _, FALSE, INFINITE)
+            WaitForMultipleObjects(handles.len() as u32, &handles as _, FALSE, timeout)
         };
         const WAIT_OBJECT_1: WAIT_EVENT = WAIT_OBJECT_0 + 1;
         match result {
-            WAIT_FAILED => Err(util::get_last_error()?.into()),
+            WAIT_FAILED => Err(self.get_last_error()?.into()),
             WAIT_OBJECT_0 => {
                 //We have data!
-                Ok(())
+                Ok(true)
             }
             WAIT_OBJECT_1 => {
                 //Shutdown event triggered
                 Err(Error::ShuttingDown)
             }
+            WAIT_TIMEOUT => Ok(false),
             _ => {
                 //This should never happen
                 panic!("WaitForMultipleObjects returned unexpected value {:?}", result);
@@ -158,6 +494,37 @@ impl Session {
         self.shutdown_event.set_event()?;
         Ok(())
     }
+
+    /// Like [`Session::shutdown`], but first waits up to `timeout` for every packet allocated with
+    /// [`Session::allocate_send_packet`] to be sent, so packets other threads are still filling in
+    /// are not lost when the session is closed.
+    ///
+    /// Packets already handed to the driver stay in the send ring until the session is dropped, so
+    /// they are not affected by the shutdown itself. Returns `Ok(false)` if the timeout elapsed
+    /// with packets still pending, readers are signaled to stop in either case.
+    ///
+    /// The blocking receive functions such as [`Session::receive_blocking`] keep returning the
+    /// packets already queued in the receive ring, and only return [`Error::ShuttingDown`] once
+    /// it is empty.
+    pub fn shutdown_graceful(&self, timeout: std::time::Duration) -> Result<bool, Error> {
+        let deadline = std::time::Instant::now() + timeout;
+        let drained = loop {
+            if self.pending_sends.load(Ordering::Acquire) == 0 {
+                break true;
+            }
+            if std::time::Instant::now() >= deadline {
+                log::warn!(
+                    "Shutting down with {} unsent packets",
+                    self.pending_sends.load(Ordering::Acquire)
+                );
+                break false;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        };
+        self.drain_on_shutdown.store(true, Ordering::Release);
+        self.shutdown()?;
+        Ok(drained)
+    }
 }
 
 impl Session {
@@ -170,7 +537,7 @@ impl Session {
         debug_assert!(size <= u16::MAX as u32);
         if ptr.is_null() {
             // Wintun returns ERROR_NO_MORE_ITEMS instead of blocking if packets are not available
-            return match unsafe { GetLastError() } {
+            return match self.take_last_error() {
                 ERROR_NO_MORE_ITEMS => Err(std::io::Error::from(std::io::ErrorKind::WouldBlock)),
                 e => Err(std::io::Error::from_raw_os_error(e as i32)),
             };
@@ -183,6 +550,7 @@ impl Session {
         }
         unsafe { ptr::copy_nonoverlapping(ptr, buf.as_mut_ptr(), size) };
         unsafe { wintun.WintunReleaseReceivePacket(self.inner.0, ptr) };
+        self.tee(&buf[..size]);
         Ok(size)
     }
 
@@ -190,10 +558,12 @@ impl Session {
     /// If the session is closed via [`Session::shutdown`] all threads currently blocking inside this function
     /// will return Err(())
     pub fn recv(&self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut drained = false;
+        let mut attempts = self.get_spin_count();
         loop {
-            // Try 5 times to receive without blocking so we don't have to issue a syscall to wait
-            // for the event if packets are being received at a rapid rate
-            for _ in 0..5 {
+            // Try several times to receive without blocking so we don't have to issue a syscall to
+            // wait for the event if packets are being received at a rapid rate, see `set_spin_count`
+            for _ in 0..attempts {
                 return match self.try_recv(buf) {
                     Ok(len) => Ok(len),
                     Err(e) => {
@@ -205,8 +575,79 @@ impl Session {
                     }
                 };
             }
-            self.wait_read()?;
+            self.wait_read_or_drain(&mut drained)?;
+            attempts = attempts.max(1);
+        }
+    }
+
+    /// Blocks until a packet is available and copies it into the buffer returned by `f`, which is
+    /// given the size of the packet, returning that size.
+    ///
+    /// This lets the caller provide storage sized exactly for the packet, e.g. from a custom
+    /// allocator or shared memory. `f` is called once, with the packet's ring slot held until it
+    /// returns. A buffer shorter than the packet fails with `InvalidInput` and drops the packet.
+    pub fn recv_with<'a>(&self, f: impl FnOnce(usize) -> &'a mut [u8]) -> std::io::Result<usize> {
+        let mut drained = false;
+        let mut f = Some(f);
+        loop {
+            let received = self.with_next_packet(|bytes| {
+                let f = f.take().expect("called once per packet received");
+                let buf = f(bytes.len());
+                if buf.len() < bytes.len() {
+                    use std::io::{Error, ErrorKind::InvalidInput};
+                    return Err(Error::new(InvalidInput, "destination buffer too small"));
+                }
+                buf[..bytes.len()].copy_from_slice(bytes);
+                Ok(bytes.len())
+            })?;
+            if let Some(result) = received {
+                return result;
+            }
+            self.wait_read_or_drain(&mut drained)?;
+        }
+    }
+
+    /// Reads queued packets into `bufs` without blocking, one packet per buffer, returning the
+    /// length of each packet read.
+    ///
+    /// Stops when the receive queue is empty, `max_packets` packets were read or at least
+    /// `max_bytes` bytes were read, so a busy session can't starve others sharing the same worker.
+    /// The byte budget is checked before each packet, so the last packet may exceed it.
+    pub fn recv_budgeted(
+        &self,
+        bufs: &mut [&mut [u8]],
+        max_packets: usize,
+        max_bytes: usize,
+    ) -> std::io::Result<Vec<usize>> {
+        let mut lens = vec![];
+        let mut total = 0;
+        for buf in bufs.iter_mut().take(max_packets) {
+            if total >= max_bytes {
+                break;
+            }
+            match self.try_recv(buf) {
+                Ok(len) => {
+                    total += len;
+                    lens.push(len);
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
         }
+        Ok(lens)
+    }
+
+    /// Blocks until a packet is available like [`Session::recv`], and reads it into the next free
+    /// buffer of `pool`. Fails if all buffers of the pool are in use.
+    ///
+    /// To receive into buffers registered up front, create the pool with
+    /// [`PacketPool::from_buffers`]. [`PoolRef::index`] tells which buffer holds the packet, and
+    /// the buffer returns to the pool when the [`PoolRef`] is dropped.
+    pub fn recv_into_pool(&self, pool: &mut PacketPool) -> std::io::Result<PoolRef> {
+        let mut slot = pool.take()?;
+        let len = self.recv(slot.buffer_mut())?;
+        slot.set_len(len);
+        Ok(slot)
     }
 
     pub fn send(&self, buf: &[u8]) -> std::io::Result<usize> {
@@ -214,12 +655,252 @@ impl Session {
         let size = buf.len();
         let ptr = unsafe { wintun.WintunAllocateSendPacket(self.inner.0, size as u32) };
         if ptr.is_null() {
-            util::get_last_error()?;
+            self.get_last_error()?;
         }
         unsafe { ptr::copy_nonoverlapping(buf.as_ptr(), ptr, size) };
+        self.tee(buf);
         unsafe { wintun.WintunSendPacket(self.inner.0, ptr) };
         Ok(buf.len())
     }
+
+    /// Sends each buffer of `bufs` as one packet, in order, returning the number of packets queued.
+    ///
+    /// If a packet can't be allocated, e.g. because the send ring is full, the remaining buffers
+    /// are not sent and the number of packets queued so far is returned. The error is only
+    /// returned if not even the first packet could be queued.
+    pub fn send_many(&self, bufs: &[&[u8]]) -> std::io::Result<usize> {
+        let wintun = &self.adapter.wintun;
+        for (sent, buf) in bufs.iter().enumerate() {
+            let ptr = unsafe { wintun.WintunAllocateSendPacket(self.inner.0, buf.len() as u32) };
+            if ptr.is_null() {
+                let e = std::io::Error::from_raw_os_error(self.take_last_error() as i32);
+                if sent == 0 {
+                    return Err(e);
+                }
+                log::trace!("Sent {} of {} packets: {}", sent, bufs.len(), e);
+                return Ok(sent);
+            }
+            unsafe { ptr::copy_nonoverlapping(buf.as_ptr(), ptr, buf.len()) };
+            self.tee(buf);
+            unsafe { wintun.WintunSendPacket(self.inner.0, ptr) };
+        }
+        Ok(bufs.len())
+    }
+}
+
+impl Session {
+    /// Forwards packets between two sessions in both directions until `running` is cleared or
+    /// either session is shut down, blocking the calling thread until then.
+    ///
+    /// A received packet is only released back to its ring once it has been copied into the other
+    /// session's send ring. If that ring is full, forwarding in this direction pauses until space
+    /// frees up, which in turn applies backpressure to the sending side.
+    pub fn bridge(a: Arc<Session>, b: Arc<Session>, running: Arc<AtomicBool>) -> Result<(), Error> {
+        let (a2, b2, running2) = (a.clone(), b.clone(), running.clone());
+        let forward = std::thread::spawn(move || Self::pump(&a2, &b2, &running2));
+        let backward = Self::pump(&b, &a, &running);
+        let forward = forward.join().map_err(|e| format!("Bridge thread panicked: {:?}", e))?;
+        forward.and(backward)
+    }
+
+    fn pump(src: &Arc<Session>, dst: &Arc<Session>, running: &AtomicBool) -> Result<(), Error> {
+        // Wake up regularly to observe `running` being cleared
+        const POLL_INTERVAL_MS: u32 = 100;
+        let result = (|| {
+            while running.load(Ordering::Relaxed) {
+                let packet = match src.try_receive()? {
+                    Some(packet) => packet,
+                    None => {
+                        src.wait_read_timeout(POLL_INTERVAL_MS)?;
+                        continue;
+                    }
+                };
+                loop {
+                    match dst.allocate_send_packet(packet.bytes.len() as u16) {
+                        Ok(mut send_packet) => {
+                            send_packet.bytes_mut().copy_from_slice(packet.bytes());
+                            dst.send_packet(send_packet);
+                            break;
+                        }
+                        Err(Error::Io(e)) if e.raw_os_error() == Some(ERROR_BUFFER_OVERFLOW as i32) => {
+                            if !running.load(Ordering::Relaxed) {
+                                return Ok(());
+                            }
+                            std::thread::sleep(std::time::Duration::from_millis(1));
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+            }
+            Ok(())
+        })();
+        // Stop the other direction as well
+        running.store(false, Ordering::Relaxed);
+        match result {
+            Err(Error::ShuttingDown) => Ok(()),
+            r => r,
+        }
+    }
+}
+
+/// The bytes of a received packet borrowed from the receive ring, returned by
+/// [`Session::try_peek_bytes`]. The ring slot is released when this is dropped.
+pub struct PeekedBytes<'a> {
+    session: &'a Session,
+    bytes: &'a [u8],
+}
+
+impl std::ops::Deref for PeekedBytes<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.bytes
+    }
+}
+
+impl Drop for PeekedBytes<'_> {
+    fn drop(&mut self) {
+        let session = self.session;
+        unsafe {
+            session
+                .adapter
+                .wintun
+                .WintunReleaseReceivePacket(session.inner.0, self.bytes.as_ptr())
+        };
+    }
+}
+
+/// Blocking iterator over the received packets of a session, returned by [`Session::iter_blocking`]
+pub struct PacketIter {
+    /// `None` once the session was shut down or an error was returned
+    session: Option<Arc<Session>>,
+}
+
+impl Iterator for PacketIter {
+    type Item = Result<packet::Packet, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = self.session.as_ref()?.receive_blocking();
+        match result {
+            Ok(packet) => Some(Ok(packet)),
+            Err(Error::ShuttingDown) => {
+                self.session = None;
+                None
+            }
+            Err(e) => {
+                self.session = None;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl std::iter::FusedIterator for PacketIter {}
+
+/// Number of packets the reader threads of a [`MultiReader`] may queue before blocking
+const MULTI_READER_CHANNEL_CAPACITY: usize = 1024;
+
+/// Packets received by several threads of a session, returned by [`Session::into_multi_reader`]
+pub struct MultiReader {
+    session: Arc<Session>,
+    receiver: std::sync::mpsc::Receiver<Vec<u8>>,
+    readers: Vec<std::thread::JoinHandle<Result<(), Error>>>,
+}
+
+impl MultiReader {
+    /// Blocks until a packet is received by any of the threads, returns `None` once all of them
+    /// have exited, e.g. after the session was shut down
+    pub fn recv(&self) -> Option<Vec<u8>> {
+        self.receiver.recv().ok()
+    }
+
+    /// Returns a packet if one is queued, without blocking
+    pub fn try_recv(&self) -> Option<Vec<u8>> {
+        self.receiver.try_recv().ok()
+    }
+
+    /// Shuts the session down and waits for the reader threads to exit, returning the first error
+    /// one of them stopped with
+    pub fn shutdown(self) -> Result<(), Error> {
+        self.session.shutdown()?;
+        // Unblock the readers waiting for room in the channel
+        drop(self.receiver);
+        let mut result = Ok(());
+        for reader in self.readers {
+            let reader_result = reader.join().unwrap_or_else(|_| Err("A reader thread panicked".into()));
+            if result.is_ok() {
+                result = reader_result;
+            }
+        }
+        result
+    }
+}
+
+/// The receiving half of a session, returned by [`Session::split`]
+#[derive(Clone)]
+pub struct PacketReader(Arc<Session>);
+
+impl PacketReader {
+    /// See [`Session::receive_blocking`]
+    pub fn receive_blocking(&self) -> Result<packet::Packet, Error> {
+        self.0.receive_blocking()
+    }
+
+    /// See [`Session::try_receive`]
+    pub fn try_receive(&self) -> Result<Option<packet::Packet>, Error> {
+        self.0.try_receive()
+    }
+
+    /// See [`Session::receive_timeout`]
+    pub fn receive_timeout(&self, timeout: std::time::Duration) -> Result<Option<packet::Packet>, Error> {
+        self.0.receive_timeout(timeout)
+    }
+
+    /// See [`Session::recv`]
+    pub fn recv(&self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.0.recv(buf)
+    }
+
+    /// See [`Session::try_recv`]
+    pub fn try_recv(&self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.0.try_recv(buf)
+    }
+
+    /// See [`Session::iter_blocking`]
+    pub fn iter_blocking(&self) -> PacketIter {
+        self.0.iter_blocking()
+    }
+
+    /// See [`Session::read_event_handle`]
+    pub fn read_event_handle(&self) -> Result<std::os::windows::io::RawHandle, Error> {
+        self.0.read_event_handle()
+    }
+}
+
+/// The sending half of a session, returned by [`Session::split`]
+#[derive(Clone)]
+pub struct PacketWriter(Arc<Session>);
+
+impl PacketWriter {
+    /// See [`Session::allocate_send_packet`]
+    pub fn allocate_send_packet(&self, size: u16) -> Result<packet::Packet, Error> {
+        self.0.allocate_send_packet(size)
+    }
+
+    /// See [`Session::send_packet`]
+    pub fn send_packet(&self, packet: packet::Packet) {
+        self.0.send_packet(packet)
+    }
+
+    /// See [`Session::send`]
+    pub fn send(&self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.send(buf)
+    }
+
+    /// See [`Session::send_many`]
+    pub fn send_many(&self, bufs: &[&[u8]]) -> std::io::Result<usize> {
+        self.0.send_many(bufs)
+    }
 }
 
 impl Drop for Session {
@@ -227,7 +908,94 @@ impl Drop for Session {
         if let Err(e) = self.shutdown() {
             log::trace!("Failed to shutdown session: {}", e);
         }
+        #[cfg(debug_assertions)]
+        self.unsent_guard.check_on_drop();
         unsafe { self.get_wintun().WintunEndSession(self.inner.0) };
         self.inner.0 = ptr::null_mut();
+        #[cfg(debug_assertions)]
+        crate::LIVE_SESSIONS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[ignore = "requires administrator privileges"]
+    fn multi_reader_stops_on_shutdown() {
+        let dll_path = crate::get_wintun_bin_pattern_path().unwrap();
+        let wintun = unsafe { crate::load_from_path(dll_path) }.unwrap();
+        let adapter = Adapter::create(&wintun, "MultiReaderTest", "Test", None).unwrap();
+        let session = adapter.start_session(crate::MAX_RING_CAPACITY).unwrap();
+
+        let reader = session.clone().into_multi_reader(4).unwrap();
+        session.shutdown().unwrap();
+        // All the reader threads exited, dropping their end of the channel
+        assert!(reader.recv().is_none());
+        reader.shutdown().unwrap();
+    }
+
+    #[test]
+    #[ignore = "requires administrator privileges"]
+    fn recv_into_registered_buffers() {
+        let dll_path = crate::get_wintun_bin_pattern_path().unwrap();
+        let wintun = unsafe { crate::load_from_path(dll_path) }.unwrap();
+        let adapter = Adapter::create(&wintun, "RegisteredTest", "Test", None).unwrap();
+        adapter.set_address("10.28.15.2".parse().unwrap()).unwrap();
+        let session = adapter.start_session(crate::MAX_RING_CAPACITY).unwrap();
+
+        let size = crate::MAX_IP_PACKET_SIZE as usize;
+        let mut pool = PacketPool::from_buffers(vec![vec![0; size], vec![0; size]]);
+        let socket = std::net::UdpSocket::bind("10.28.15.2:0").unwrap();
+        socket.send_to(b"registered", "10.28.15.3:9").unwrap();
+
+        let mut held = vec![];
+        loop {
+            let packet = session.recv_into_pool(&mut pool).unwrap();
+            // The IPv4 UDP datagram to 10.28.15.3, ignoring the other traffic Windows sends
+            if packet[0] >> 4 == 4 && packet[9] == 17 && packet[16..20] == [10, 28, 15, 3] {
+                assert!(packet.ends_with(b"registered"));
+                assert!(packet.index() < 2);
+                break;
+            }
+            // Keep one buffer in use at times, so both registered buffers get filled
+            if held.is_empty() {
+                held.push(packet);
+            } else {
+                held.clear();
+            }
+        }
+        drop(held);
+        assert_eq!(pool.available(), 2);
+    }
+
+    #[test]
+    #[ignore = "requires administrator privileges"]
+    fn graceful_shutdown_drains_queued_packets() {
+        let dll_path = crate::get_wintun_bin_pattern_path().unwrap();
+        let wintun = unsafe { crate::load_from_path(dll_path) }.unwrap();
+        let adapter = Adapter::create(&wintun, "DrainTest", "Test", None).unwrap();
+        adapter.set_address("10.28.14.2".parse().unwrap()).unwrap();
+        let session = adapter.start_session(crate::MAX_RING_CAPACITY).unwrap();
+
+        // Datagrams to a peer on the tunnel's subnet are routed into the receive ring
+        let socket = std::net::UdpSocket::bind("10.28.14.2:0").unwrap();
+        for _ in 0..100 {
+            socket.send_to(b"drain", "10.28.14.3:9").unwrap();
+        }
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        session.shutdown_graceful(std::time::Duration::from_secs(1)).unwrap();
+
+        let mut received = 0;
+        for packet in session.iter_blocking() {
+            let packet = packet.unwrap();
+            let bytes = packet.bytes();
+            // IPv4 UDP datagrams to 10.28.14.3, ignoring the other traffic Windows sends
+            if bytes[0] >> 4 == 4 && bytes[9] == 17 && bytes[16..20] == [10, 28, 14, 3] {
+                received += 1;
+            }
+        }
+        assert_eq!(received, 100);
     }
 }
@@ -1,23 +1,30 @@
-use crate::Error;
-use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use crate::{AddressFamily, Error, RouteEntry};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use windows_sys::{
     core::GUID,
     Win32::{
         Foundation::{
-            GetLastError, LocalFree, ERROR_ADDRESS_NOT_ASSOCIATED, ERROR_BUFFER_OVERFLOW, ERROR_INSUFFICIENT_BUFFER,
-            ERROR_INVALID_PARAMETER, ERROR_NOT_ENOUGH_MEMORY, ERROR_NO_DATA, ERROR_SUCCESS, NO_ERROR, WIN32_ERROR,
+            GetLastError, LocalFree, ERROR_BUFFER_OVERFLOW, ERROR_INSUFFICIENT_BUFFER, ERROR_SUCCESS, NO_ERROR,
+            WIN32_ERROR,
         },
         NetworkManagement::{
+            IpHelper::{
+                CreateUnicastIpAddressEntry, GetIfEntry2, GetIpInterfaceEntry, GetIpInterfaceTable,
+                GetUnicastIpAddressEntry, InitializeIpInterfaceEntry, InitializeUnicastIpAddressEntry,
+                SetIpInterfaceEntry, SetUnicastIpAddressEntry, MIB_IF_ROW2, MIB_IPINTERFACE_ROW, MIB_IPINTERFACE_TABLE,
+                MIB_UNICASTIPADDRESS_ROW,
+            },
             IpHelper::{
                 FreeMibTable, GetAdaptersAddresses, GetInterfaceInfo, DNS_INTERFACE_SETTINGS,
-                DNS_INTERFACE_SETTINGS_VERSION1, DNS_SETTING_NAMESERVER, GAA_FLAG_INCLUDE_GATEWAYS,
-                GAA_FLAG_INCLUDE_PREFIX, IF_TYPE_ETHERNET_CSMACD, IF_TYPE_IEEE80211, IP_ADAPTER_ADDRESSES_LH,
-                IP_ADAPTER_INDEX_MAP, IP_INTERFACE_INFO,
+                DNS_INTERFACE_SETTINGS_VERSION1, DNS_SETTING_DOMAIN, DNS_SETTING_IPV6, DNS_SETTING_NAMESERVER,
+                DNS_SETTING_SEARCHLIST, GAA_FLAG_INCLUDE_GATEWAYS, GAA_FLAG_INCLUDE_PREFIX, IF_TYPE_ETHERNET_CSMACD,
+                IF_TYPE_IEEE80211, IP_ADAPTER_ADDRESSES_LH, IP_ADAPTER_INDEX_MAP, IP_INTERFACE_INFO,
             },
-            IpHelper::{GetIpInterfaceTable, MIB_IPINTERFACE_ROW, MIB_IPINTERFACE_TABLE},
-            Ndis::IfOperStatusUp,
+            Ndis::{IfOperStatusUp, NET_LUID_LH},
+        },
+        Networking::WinSock::{
+            AF_INET, AF_INET6, AF_UNSPEC, SOCKADDR, SOCKADDR_IN, SOCKADDR_IN6, SOCKADDR_INET, SOCKET_ADDRESS,
         },
-        Networking::WinSock::{AF_INET, AF_INET6, AF_UNSPEC, SOCKADDR, SOCKADDR_IN, SOCKADDR_IN6, SOCKET_ADDRESS},
         System::{
             Com::StringFromGUID2,
             Diagnostics::Debug::{FormatMessageW, FORMAT_MESSAGE_ALLOCATE_BUFFER, FORMAT_MESSAGE_FROM_SYSTEM},
@@ -26,7 +33,7 @@ use windows_sys::{
     },
 };
 
-pub fn get_wintun_bin_pattern_path() -> std::io::Result<std::path::PathBuf> {
+pub fn get_wintun_bin_pattern_path() -> Result<std::path::PathBuf, Error> {
     let dll_path = if cfg!(target_arch = "x86") {
         "wintun/bin/x86/wintun.dll"
     } else if cfg!(target_arch = "x86_64") {
@@ -36,10 +43,7 @@ pub fn get_wintun_bin_pattern_path() -> std::io::Result<std::path::PathBuf> {
     } else if cfg!(target_arch = "aarch64") {
         "wintun/bin/arm64/wintun.dll"
     } else {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "Unsupported architecture",
-        ));
+        return Err(Error::UnsupportedArchitecture(std::env::consts::ARCH.to_string()));
     };
     Ok(dll_path.into())
 }
@@ -68,6 +72,92 @@ pub(crate) const fn win_guid_to_u128(guid: &GUID) -> u128 {
     ((guid.data1 as u128) << 96) | ((guid.data2 as u128) << 80) | ((guid.data3 as u128) << 64) | (data4_u64 as u128)
 }
 
+pub(crate) fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for chunk in message.chunks_exact(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in chunk.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, wi) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*wi);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (h, v) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(v);
+        }
+    }
+
+    let mut digest = [0u8; 20];
+    for (chunk, v) in digest.chunks_exact_mut(4).zip(h) {
+        chunk.copy_from_slice(&v.to_be_bytes());
+    }
+    digest
+}
+
+/// Derives a name-based UUID (version 5, RFC 4122) from `namespace` and `name`
+pub(crate) fn uuid_v5(namespace: u128, name: &[u8]) -> u128 {
+    let mut data = namespace.to_be_bytes().to_vec();
+    data.extend_from_slice(name);
+    let hash = sha1(&data);
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&hash[..16]);
+    bytes[6] = (bytes[6] & 0x0f) | 0x50;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    u128::from_be_bytes(bytes)
+}
+
+/// The `IMAGE_FILE_MACHINE_*` value a dll built for the architecture of this process has
+pub(crate) const PROCESS_DLL_MACHINE: u16 = if cfg!(target_arch = "x86") {
+    0x014c
+} else if cfg!(target_arch = "x86_64") {
+    0x8664
+} else if cfg!(target_arch = "arm") {
+    0x01c4
+} else {
+    0xaa64
+};
+
+/// Returns the `Machine` field of the PE header of the dll image `bytes`, or `None` if it is not a
+/// PE image
+pub(crate) fn dll_machine(bytes: &[u8]) -> Option<u16> {
+    if bytes.get(..2)? != b"MZ" {
+        return None;
+    }
+    let pe_offset = u32::from_le_bytes(bytes.get(0x3c..0x40)?.try_into().ok()?) as usize;
+    let pe_header = bytes.get(pe_offset..pe_offset.checked_add(6)?)?;
+    if &pe_header[..4] != b"PE\0\0" {
+        return None;
+    }
+    Some(u16::from_le_bytes([pe_header[4], pe_header[5]]))
+}
+
 pub(crate) unsafe fn win_pstr_to_string(pstr: ::windows_sys::core::PSTR) -> Result<String, Error> {
     Ok(std::ffi::CStr::from_ptr(pstr as *const std::ffi::c_char)
         .to_str()
@@ -88,10 +178,9 @@ pub(crate) unsafe fn win_pwstr_to_string(pwstr: ::windows_sys::core::PWSTR) -> R
     let slice = std::slice::from_raw_parts(pwstr, len);
 
     use std::os::windows::ffi::OsStringExt;
+    // Unpaired surrogates are replaced, so a localized message never fails to decode
     let os_string = std::ffi::OsString::from_wide(slice);
-    os_string
-        .into_string()
-        .map_err(|e| format!("Invalid UTF-8 sequence: {:?}", e).into())
+    Ok(os_string.to_string_lossy().into_owned())
 }
 
 pub(crate) fn guid_to_win_style_string(guid: &GUID) -> Result<String, Error> {
@@ -101,6 +190,14 @@ pub(crate) fn guid_to_win_style_string(guid: &GUID) -> Result<String, Error> {
     Ok(guid)
 }
 
+pub(crate) fn ipv4_netmask_for_prefix(prefix: u8) -> Result<Ipv4Addr, &'static str> {
+    if prefix > 32 {
+        return Err("Prefix value must be between 0 and 32.");
+    }
+    let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+    Ok(Ipv4Addr::from(mask))
+}
+
 pub(crate) fn ipv6_netmask_for_prefix(prefix: u8) -> Result<Ipv6Addr, &'static str> {
     if prefix > 128 {
         return Err("Prefix value must be between 0 and 128.");
@@ -151,6 +248,155 @@ pub fn get_active_network_interface_gateways() -> std::io::Result<Vec<IpAddr>> {
     Ok(addrs)
 }
 
+/// Returns the gateway addresses of the given family of the active Ethernet and Wi-Fi interfaces,
+/// together with the interface index each gateway belongs to, e.g. for building bypass routes.
+pub fn get_active_network_interface_gateways_by_family(is_ipv6: bool) -> std::io::Result<Vec<(u32, IpAddr)>> {
+    let mut gateways = vec![];
+    get_adapters_addresses(|adapter| {
+        if adapter.OperStatus == IfOperStatusUp
+            && [IF_TYPE_IEEE80211, IF_TYPE_ETHERNET_CSMACD].contains(&adapter.IfType)
+        {
+            let index = if is_ipv6 {
+                adapter.Ipv6IfIndex
+            } else {
+                unsafe { adapter.Anonymous1.Anonymous.IfIndex }
+            };
+            let mut current_gateway = adapter.FirstGatewayAddress;
+            while !current_gateway.is_null() {
+                let gateway = unsafe { &*current_gateway };
+                match retrieve_ipaddr_from_socket_address(&gateway.Address) {
+                    Ok(addr) if addr.is_ipv6() == is_ipv6 => gateways.push((index, addr)),
+                    Ok(_) => {}
+                    Err(e) => {
+                        log::error!("Failed to convert sockaddr to socket address: {}", e);
+                        return false;
+                    }
+                }
+                current_gateway = gateway.Next;
+            }
+        }
+        true
+    })?;
+    Ok(gateways)
+}
+
+/// Returns the gateway addresses of the active Ethernet and Wi-Fi interfaces as
+/// `(interface_index, gateway)` pairs, of `family` only if given, see
+/// [`get_active_network_interface_gateways_by_family`]
+pub fn get_default_gateways(family: Option<AddressFamily>) -> std::io::Result<Vec<(u32, IpAddr)>> {
+    let mut gateways = vec![];
+    if family != Some(AddressFamily::Ipv6) {
+        gateways.extend(get_active_network_interface_gateways_by_family(false)?);
+    }
+    if family != Some(AddressFamily::Ipv4) {
+        gateways.extend(get_active_network_interface_gateways_by_family(true)?);
+    }
+    Ok(gateways)
+}
+
+/// Returns the IPv4 and IPv6 default routes of all interfaces, ordered by ascending effective
+/// metric (route metric plus interface metric), so the route Windows prefers comes first.
+///
+/// Useful to detect whether the tunnel's default route is shadowed by another adapter's route
+/// with a lower metric, or is unexpectedly winning over the LAN's.
+pub fn find_conflicting_default_routes() -> std::io::Result<Vec<RouteEntry>> {
+    let mut routes = vec![];
+    let mut error = None;
+    crate::route::get_ip_forward_table(|row| {
+        if row.DestinationPrefix.PrefixLength != 0 {
+            return true;
+        }
+        match RouteEntry::from_row(row) {
+            Ok(route) => routes.push(route),
+            Err(e) => {
+                error = Some(e);
+                return false;
+            }
+        }
+        true
+    })?;
+    if let Some(e) = error {
+        return Err(e);
+    }
+    routes.sort_by_key(|route| route.effective_metric);
+    Ok(routes)
+}
+
+/// Returns whether Windows generates IPv6 temporary (privacy) addresses, using PowerShell's
+/// `Get-NetIPv6Protocol`, see [`set_privacy_addresses`].
+pub fn get_privacy_addresses() -> Result<bool, Error> {
+    // command line: `powershell -NoProfile -Command "(Get-NetIPv6Protocol).UseTemporaryAddresses"`
+    let out = run_command(
+        "powershell",
+        &[
+            "-NoProfile",
+            "-NonInteractive",
+            "-Command",
+            "(Get-NetIPv6Protocol).UseTemporaryAddresses",
+        ],
+    )?;
+    Ok(String::from_utf8_lossy(&out).trim() != "Disabled")
+}
+
+/// Enables or disables IPv6 temporary (privacy) addresses, using PowerShell's
+/// `Set-NetIPv6Protocol`, so that packets leave the tunnel with a predictable source address.
+///
+/// **This setting is system-wide and affects every adapter, not only the WinTun one.** Windows
+/// has no per-interface switch for temporary addresses, neither `MIB_IPINTERFACE_ROW` nor
+/// `netsh interface ipv6 set privacy` accept an interface. Callers should restore the previous
+/// value from [`get_privacy_addresses`] when the tunnel goes down. Requires administrator privileges.
+pub fn set_privacy_addresses(enabled: bool) -> Result<(), Error> {
+    let state = if enabled { "Enabled" } else { "Disabled" };
+    // command line: `powershell -NoProfile -Command "Set-NetIPv6Protocol -UseTemporaryAddresses Disabled"`
+    let script = format!("Set-NetIPv6Protocol -UseTemporaryAddresses {}", state);
+    run_command("powershell", &["-NoProfile", "-NonInteractive", "-Command", &script])?;
+    Ok(())
+}
+
+/// Service names of NDIS filter drivers known to drop or mangle the traffic of WinTun adapters
+#[cfg(feature = "winreg")]
+const KNOWN_CONFLICTING_FILTER_DRIVERS: &[&str] = &[
+    "vsdatant", // Check Point Endpoint Security / ZoneAlarm firewall
+    "ndisrd",   // WinpkFilter, bundled by several VPN and traffic shaping clients
+    "epfwlwf",  // ESET firewall
+];
+
+/// Best-effort diagnostic returning the installed NDIS filter drivers known to conflict with
+/// WinTun, as `"<description> (<service>)"`, or an empty list if there are none.
+///
+/// The filters are read from the network service class in the registry, whether or not they are
+/// currently bound to an adapter. Useful when the tunnel is up but no traffic flows through it.
+#[cfg(feature = "winreg")]
+pub fn detect_conflicting_filter_drivers() -> std::io::Result<Vec<String>> {
+    use winreg::{enums::HKEY_LOCAL_MACHINE, RegKey};
+    let hklm = RegKey::predef(HKEY_LOCAL_MACHINE);
+    let filters =
+        hklm.open_subkey("SYSTEM\\CurrentControlSet\\Control\\Network\\{4d36e974-e325-11ce-bfc1-08002be10318}")?;
+
+    let mut conflicting = vec![];
+    for sub_key_name in filters.enum_keys().filter_map(Result::ok) {
+        let Ok(component) = filters.open_subkey(&sub_key_name) else {
+            continue;
+        };
+        let Ok(service) = component
+            .open_subkey("Ndi")
+            .and_then(|ndi| ndi.get_value::<String, _>("Service"))
+        else {
+            continue;
+        };
+        if !KNOWN_CONFLICTING_FILTER_DRIVERS
+            .iter()
+            .any(|known| known.eq_ignore_ascii_case(&service))
+        {
+            continue;
+        }
+        let description = component.get_value::<String, _>("Description").unwrap_or_default();
+        log::debug!("Found conflicting filter driver {} ({})", description, service);
+        conflicting.push(format!("{} ({})", description, service));
+    }
+    Ok(conflicting)
+}
+
 crate::define_fn_dynamic_load!(
     SetInterfaceDnsSettingsDeclare,
     unsafe extern "system" fn(GUID, *const DNS_INTERFACE_SETTINGS) -> WIN32_ERROR,
@@ -160,16 +406,38 @@ crate::define_fn_dynamic_load!(
     "SetInterfaceDnsSettings"
 );
 
+/// Splits `dns` into `(ipv4, ipv6)` server lists, keeping the order within each family
+pub(crate) fn split_dns_servers_by_family(dns: &[IpAddr]) -> (Vec<IpAddr>, Vec<IpAddr>) {
+    dns.iter().partition(|ip| ip.is_ipv4())
+}
+
+/// Sets the DNS servers of both families, with one `SetInterfaceDnsSettings` call per family,
+/// since Windows doesn't accept IPv4 and IPv6 servers mixed in a single call
 pub(crate) fn set_interface_dns_servers(interface: GUID, dns: &[IpAddr]) -> crate::Result<()> {
+    let (v4, v6) = split_dns_servers_by_family(dns);
+    if !v4.is_empty() || v6.is_empty() {
+        set_interface_dns_servers_of_family(interface, &v4, false)?;
+    }
+    if !v6.is_empty() {
+        set_interface_dns_servers_of_family(interface, &v6, true)?;
+    }
+    Ok(())
+}
+
+fn set_interface_dns_servers_of_family(interface: GUID, dns: &[IpAddr], is_ipv6: bool) -> crate::Result<()> {
     let func = SetInterfaceDnsSettings().ok_or("Failed to load function SetInterfaceDnsSettings")?;
 
     // format L"1.1.1.1,8.8.8.8", or L"1.1.1.1 8.8.8.8".
     let dns = dns.iter().map(|ip| ip.to_string()).collect::<Vec<_>>().join(",");
     let dns = dns.encode_utf16().chain(std::iter::once(0)).collect::<Vec<_>>();
 
+    let mut flags = DNS_SETTING_NAMESERVER;
+    if is_ipv6 {
+        flags |= DNS_SETTING_IPV6;
+    }
     let settings = DNS_INTERFACE_SETTINGS {
         Version: DNS_INTERFACE_SETTINGS_VERSION1,
-        Flags: DNS_SETTING_NAMESERVER as _,
+        Flags: flags as _,
         NameServer: dns.as_ptr() as _,
         Domain: std::ptr::null_mut(),
         SearchList: std::ptr::null_mut(),
@@ -180,34 +448,82 @@ pub(crate) fn set_interface_dns_servers(interface: GUID, dns: &[IpAddr]) -> crat
         ProfileNameServer: std::ptr::null_mut(),
     };
 
+    let interface_s = format!("interface={}", guid_to_win_style_string(&interface)?);
+    let family_s = format!("family={}", if is_ipv6 { "ipv6" } else { "ipv4" });
+    let name_server_s = format!("name_server={}", decode_utf16(&dns));
+    notify_command_observer("SetInterfaceDnsSettings", &[&interface_s, &family_s, &name_server_s]);
+
     // The SetInterfaceDnsSettings function was first introduced in Windows 10,
     // to compatible with Windows 7, we use the dynamic loading method to call the function.
     // unsafe { SetInterfaceDnsSettings(interface, &settings as *const _) }
     match unsafe { func(interface, &settings as *const _) } {
         0 => Ok(()),
-        e => Err(std::io::Error::from_raw_os_error(e as i32).into()),
+        e => Err(Error::from_win32(e, "SetInterfaceDnsSettings")),
     }
 }
 
-pub(crate) fn set_interface_dns_servers_via_cmd(adapter: &str, dns: &[IpAddr]) -> crate::Result<()> {
-    if dns.is_empty() {
-        return Ok(());
+/// Sets the connection-specific DNS suffix to the first of `domains`, and the DNS search list of
+/// the interface to all of them, with `SetInterfaceDnsSettings`
+pub(crate) fn set_interface_dns_domains(interface: GUID, domains: &[&str]) -> crate::Result<()> {
+    let func = SetInterfaceDnsSettings().ok_or("Failed to load function SetInterfaceDnsSettings")?;
+
+    let domain = domains.first().copied().unwrap_or_default();
+    let domain = domain.encode_utf16().chain(std::iter::once(0)).collect::<Vec<_>>();
+    // format L"corp.example.com,example.com"
+    let search_list = domains.join(",");
+    let search_list = search_list.encode_utf16().chain(std::iter::once(0)).collect::<Vec<_>>();
+
+    let settings = DNS_INTERFACE_SETTINGS {
+        Version: DNS_INTERFACE_SETTINGS_VERSION1,
+        Flags: (DNS_SETTING_DOMAIN | DNS_SETTING_SEARCHLIST) as _,
+        NameServer: std::ptr::null_mut(),
+        Domain: domain.as_ptr() as _,
+        SearchList: search_list.as_ptr() as _,
+        RegistrationEnabled: 0,
+        RegisterAdapterName: 0,
+        EnableLLMNR: 0,
+        QueryAdapterName: 0,
+        ProfileNameServer: std::ptr::null_mut(),
+    };
+
+    let interface_s = format!("interface={}", guid_to_win_style_string(&interface)?);
+    let domain_s = format!("domain={}", decode_utf16(&domain));
+    let search_list_s = format!("search_list={}", decode_utf16(&search_list));
+    notify_command_observer("SetInterfaceDnsSettings", &[&interface_s, &domain_s, &search_list_s]);
+
+    match unsafe { func(interface, &settings as *const _) } {
+        0 => Ok(()),
+        e => Err(Error::from_win32(e, "SetInterfaceDnsSettings")),
     }
-    let ip_str = if dns[0].is_ipv4() { "ipv4" } else { "ipv6" };
-
-    // netsh interface ipv4 set dns name="MyAdapter" source="static" address="8.8.8.8"
-    // netsh interface ipv4 add dns name="MyAdapter" index=2 address="8.8.4.4"
-    let name = format!("name=\"{}\"", adapter);
-    let addr = format!("address=\"{}\"", dns[0]);
-    let args = vec!["interface", ip_str, "set", "dns", &name, "source=\"static\"", &addr];
-    run_command("netsh", &args)?;
-    let mut index = 2;
-    for dns in dns.iter().skip(1) {
-        let addr = format!("address=\"{}\"", dns);
-        let idx = format!("index={}", index);
-        let args = vec!["interface", ip_str, "add", "dns", &name, &idx, &addr];
+}
+
+/// Sets the connection-specific DNS suffix of the interface `index` with command `wmic`, for
+/// Windows versions without `SetInterfaceDnsSettings`, which have no per-interface search list
+pub(crate) fn set_interface_dns_domain_via_cmd(index: u32, domain: &str) -> crate::Result<()> {
+    // command line: `wmic nicconfig where "InterfaceIndex=12" call SetDNSDomain "corp.example.com"`
+    let filter = format!("InterfaceIndex={}", index);
+    run_command("wmic", &["nicconfig", "where", &filter, "call", "SetDNSDomain", domain])?;
+    Ok(())
+}
+
+pub(crate) fn set_interface_dns_servers_via_cmd(adapter: &str, dns: &[IpAddr]) -> crate::Result<()> {
+    let (v4, v6) = split_dns_servers_by_family(dns);
+    for (ip_str, dns) in [("ipv4", v4), ("ipv6", v6)] {
+        if dns.is_empty() {
+            continue;
+        }
+        // netsh interface ipv4 set dns name="MyAdapter" source="static" address="8.8.8.8"
+        // netsh interface ipv4 add dns name="MyAdapter" index=2 address="8.8.4.4"
+        let name = format!("name=\"{}\"", adapter);
+        let addr = format!("address=\"{}\"", dns[0]);
+        let args = vec!["interface", ip_str, "set", "dns", &name, "source=\"static\"", &addr];
         run_command("netsh", &args)?;
-        index += 1;
+        for (index, dns) in dns.iter().enumerate().skip(1) {
+            let addr = format!("address=\"{}\"", dns);
+            let idx = format!("index={}", index + 1);
+            let args = vec!["interface", ip_str, "add", "dns", &name, &idx, &addr];
+            run_command("netsh", &args)?;
+        }
     }
 
     Ok(())
@@ -240,6 +556,30 @@ pub(crate) unsafe fn sockaddr_in6_to_socket_addr(sockaddr_in6: &SOCKADDR_IN6) ->
     SocketAddr::new(ip, port)
 }
 
+pub(crate) unsafe fn sockaddr_inet_to_ip_addr(sockaddr_inet: &SOCKADDR_INET) -> std::io::Result<IpAddr> {
+    let address = match sockaddr_inet.si_family {
+        AF_INET => sockaddr_in_to_socket_addr(&sockaddr_inet.Ipv4).ip(),
+        AF_INET6 => sockaddr_in6_to_socket_addr(&sockaddr_inet.Ipv6).ip(),
+        _ => return Err(std::io::Error::other("Unsupported address type")),
+    };
+    Ok(address)
+}
+
+pub(crate) fn ip_addr_to_sockaddr_inet(address: &IpAddr) -> SOCKADDR_INET {
+    let mut sockaddr_inet: SOCKADDR_INET = unsafe { std::mem::zeroed() };
+    match address {
+        IpAddr::V4(addr) => {
+            sockaddr_inet.Ipv4.sin_family = AF_INET;
+            sockaddr_inet.Ipv4.sin_addr.S_un.S_addr = u32::from_ne_bytes(addr.octets());
+        }
+        IpAddr::V6(addr) => {
+            sockaddr_inet.Ipv6.sin6_family = AF_INET6;
+            sockaddr_inet.Ipv6.sin6_addr.u.Byte = addr.octets();
+        }
+    }
+    sockaddr_inet
+}
+
 pub(crate) fn get_adapters_addresses<F>(mut callback: F) -> Result<(), Error>
 where
     F: FnMut(IP_ADAPTER_ADDRESSES_LH) -> bool,
@@ -253,7 +593,7 @@ where
     let result = unsafe { GetAdaptersAddresses(family, flags, std::ptr::null_mut(), std::ptr::null_mut(), &mut size) };
 
     if result != ERROR_BUFFER_OVERFLOW {
-        return Err(format!("GetAdaptersAddresses first attemp failed: {}", format_message(result)?).into());
+        return Err(Error::from_win32(result, "GetAdaptersAddresses"));
     }
     // Allocate memory for the buffer
     let mut addresses: Vec<u8> = vec![0; (size + 4) as usize];
@@ -265,16 +605,7 @@ where
     };
 
     if ERROR_SUCCESS != result {
-        let err_msg = match result {
-            ERROR_ADDRESS_NOT_ASSOCIATED => "ERROR_ADDRESS_NOT_ASSOCIATED".into(),
-            ERROR_BUFFER_OVERFLOW => "ERROR_BUFFER_OVERFLOW".into(),
-            ERROR_INVALID_PARAMETER => "ERROR_INVALID_PARAMETER".into(),
-            ERROR_NOT_ENOUGH_MEMORY => "ERROR_NOT_ENOUGH_MEMORY".into(),
-            ERROR_NO_DATA => "ERROR_NO_DATA".into(),
-            _ => format_message(result)?,
-        };
-
-        return Err(format!("GetAdaptersAddresses second attemp failed: {err_msg}").into());
+        return Err(Error::from_win32(result, "GetAdaptersAddresses"));
     }
 
     // If successful, output some information from the data we received
@@ -300,9 +631,9 @@ where
     //stack memory
     let result = unsafe { GetInterfaceInfo(std::ptr::null_mut(), &mut buf_len as *mut u32) };
     if result != NO_ERROR && result != ERROR_INSUFFICIENT_BUFFER {
-        let err_msg = format_message(result).map_err(Error::from)?;
-        log::error!("Failed to get interface info: {}", err_msg);
-        return Err(format!("GetInterfaceInfo failed: {}", err_msg).into());
+        let err = Error::from_win32(result, "GetInterfaceInfo");
+        log::error!("Failed to get interface info: {}", err);
+        return Err(err);
     }
 
     //Allocate a buffer of the requested size
@@ -331,16 +662,16 @@ where
         )
     };
     if result != NO_ERROR {
-        let err_msg = format_message(result).map_err(Error::from)?;
+        let err = Error::from_win32(result, "GetInterfaceInfo");
         //TODO: maybe over allocate the buffer in case the needed size changes between the two
         //calls to GetInterfaceInfo if another adapter is added
         log::error!(
             "Failed to get interface info a second time: {}. Original len: {}, final len: {}",
-            err_msg,
+            err,
             buf_len,
             final_buf_len
         );
-        return Err(format!("GetInterfaceInfo failed a second time: {}", err_msg).into());
+        return Err(err);
     }
     let info = buf.as_mut_ptr() as *const IP_INTERFACE_INFO;
     //SAFETY:
@@ -371,7 +702,6 @@ where
     Ok(())
 }
 
-#[allow(dead_code)]
 pub(crate) fn get_interface_info() -> Result<Vec<(u32, String)>, Error> {
     let mut v = vec![];
     get_interface_info_sys(|mut interface| {
@@ -402,8 +732,33 @@ fn MAKELANGID(p: u32, s: u32) -> u32 {
     ((s & 0x0000ffff) << 10) | (p & 0x0000ffff)
 }
 
+/// Maximum number of messages kept by [`format_message`]'s cache
+const FORMAT_MESSAGE_CACHE_SIZE: usize = 64;
+
+static FORMAT_MESSAGE_CACHE: std::sync::LazyLock<std::sync::Mutex<std::collections::HashMap<u32, String>>> =
+    std::sync::LazyLock::new(|| std::sync::Mutex::new(std::collections::HashMap::new()));
+
 /// Returns a a human readable error message from a windows error code
+///
+/// Messages are cached, so repeated errors with the same code don't call `FormatMessageW` again.
 pub fn format_message(error_code: u32) -> std::io::Result<String> {
+    if let Some(msg) = FORMAT_MESSAGE_CACHE
+        .lock()
+        .ok()
+        .and_then(|cache| cache.get(&error_code).cloned())
+    {
+        return Ok(msg);
+    }
+    let result = format_message_sys(error_code)?;
+    if let Ok(mut cache) = FORMAT_MESSAGE_CACHE.lock() {
+        if cache.len() < FORMAT_MESSAGE_CACHE_SIZE {
+            cache.insert(error_code, result.clone());
+        }
+    }
+    Ok(result)
+}
+
+fn format_message_sys(error_code: u32) -> std::io::Result<String> {
     let buf: *mut u16 = std::ptr::null_mut();
 
     let chars_written = unsafe {
@@ -442,13 +797,47 @@ pub(crate) fn get_os_error_from_id(id: i32) -> std::io::Result<()> {
 }
 
 pub fn set_adapter_mtu(name: &str, mtu: usize, is_ipv6: bool) -> std::io::Result<()> {
-    if let Err(e) = set_adapter_mtu_cmd(name, mtu, is_ipv6) {
-        log::error!("Failed to set MTU for adapter: {}", e);
-        set_adapter_mtu_api(name, mtu)?;
+    let luid = crate::ffi::alias_to_luid(name)?;
+    set_adapter_mtu_by_luid(&luid, name, mtu, &[is_ipv6])
+}
+
+/// Sets the MTU of the address families `is_ipv6` through `SetIpInterfaceEntry`, falling back to
+/// `netsh` only if that fails for the first family.
+///
+/// All families are set the same way, so they don't end up with different persistence, if the
+/// API fails after the first family was set the error is returned rather than mixing mechanisms.
+pub(crate) fn set_adapter_mtu_by_luid(
+    luid: &NET_LUID_LH,
+    name: &str,
+    mtu: usize,
+    is_ipv6: &[bool],
+) -> std::io::Result<()> {
+    let Some((first, rest)) = is_ipv6.split_first() else {
+        return Ok(());
+    };
+    if let Err(e) = set_ip_interface_mtu(luid, mtu, *first) {
+        log::warn!(
+            "Failed to set MTU through SetIpInterfaceEntry, falling back to netsh: {}",
+            e
+        );
+        for is_ipv6 in is_ipv6 {
+            set_adapter_mtu_cmd(name, mtu, *is_ipv6)?;
+        }
+        return Ok(());
+    }
+    for is_ipv6 in rest {
+        set_ip_interface_mtu(luid, mtu, *is_ipv6)?;
     }
     Ok(())
 }
 
+fn set_ip_interface_mtu(luid: &NET_LUID_LH, mtu: usize, is_ipv6: bool) -> std::io::Result<()> {
+    let mtu = u32::try_from(mtu).map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "MTU too large"))?;
+    let mut row = get_ip_interface_entry(luid, is_ipv6)?;
+    row.NlMtu = mtu;
+    Ok(set_ip_interface_entry(&mut row, &format!("mtu={}", mtu))?)
+}
+
 pub fn set_adapter_mtu_cmd(name: &str, mtu: usize, is_ipv6: bool) -> std::io::Result<()> {
     // command line: `netsh interface ipv4 set subinterface "MyAdapter" mtu=1500`
     let ip_str = if is_ipv6 { "ipv6" } else { "ipv4" };
@@ -464,33 +853,35 @@ pub fn set_adapter_mtu_cmd(name: &str, mtu: usize, is_ipv6: bool) -> std::io::Re
     Ok(())
 }
 
-/// FIXME: This function perhapes is not working as expected, so don't use it for now.
-pub fn set_adapter_mtu_api(name: &str, mtu: usize) -> std::io::Result<()> {
-    use windows_sys::Win32::NetworkManagement::IpHelper::{GetIfEntry, SetIfEntry, MIB_IFROW};
-    let luid = crate::ffi::alias_to_luid(name)?;
-    let index = crate::ffi::luid_to_index(&luid)?;
+type CommandObserver = Box<dyn Fn(&str, &[&str]) + Send + Sync>;
 
-    let mut row: MIB_IFROW = unsafe { std::mem::zeroed() };
-    row.dwIndex = index;
+static COMMAND_OBSERVER: std::sync::RwLock<Option<CommandObserver>> = std::sync::RwLock::new(None);
 
-    let v0 = unsafe { GetIfEntry(&mut row) };
-    if v0 != NO_ERROR {
-        let info = format_message(v0)?;
-        return Err(std::io::Error::new(std::io::ErrorKind::Other, info));
+/// Registers an observer that is invoked with every system change this crate makes, for auditing.
+///
+/// Commands executed by [`run_command`] (such as `netsh`) are reported with their exact arguments
+/// before execution. Changes made through the Win32 API are reported with the API function name as
+/// `command` and a `key=value` description of the change as `args`.
+pub fn set_command_observer(f: impl Fn(&str, &[&str]) + Send + Sync + 'static) {
+    match COMMAND_OBSERVER.write() {
+        Ok(mut observer) => *observer = Some(Box::new(f)),
+        Err(e) => log::error!("Failed to set command observer: {}", e),
     }
-    row.dwMtu = mtu as u32;
-    let v2 = unsafe { SetIfEntry(&row) };
-    if v2 != NO_ERROR {
-        let info = format_message(v2)?;
-        return Err(std::io::Error::new(std::io::ErrorKind::Other, info));
+}
+
+pub(crate) fn notify_command_observer(command: &str, args: &[&str]) {
+    if let Ok(observer) = COMMAND_OBSERVER.read() {
+        if let Some(observer) = observer.as_ref() {
+            observer(command, args);
+        }
     }
-    Ok(())
 }
 
 /// Runs a command and returns an error if the command fails, just convenience for users.
 pub fn run_command(command: &str, args: &[&str]) -> std::io::Result<Vec<u8>> {
     let full_cmd = format!("{} {}", command, args.join(" "));
     log::debug!("Running command: \"{full_cmd}\"...");
+    notify_command_observer(command, args);
     let out = match std::process::Command::new(command).args(args).output() {
         Ok(out) => out,
         Err(e) => {
@@ -500,7 +891,7 @@ pub fn run_command(command: &str, args: &[&str]) -> std::io::Result<Vec<u8>> {
         }
     };
     if !out.status.success() {
-        let err = String::from_utf8_lossy(if out.stderr.is_empty() {
+        let err = decode_oem_output(if out.stderr.is_empty() {
             &out.stdout
         } else {
             &out.stderr
@@ -564,6 +955,120 @@ pub(crate) fn get_mtu_by_index(index: u32, is_ipv6: bool) -> std::io::Result<u32
     Ok(mtu)
 }
 
+/// The smallest IPv4 MTU Windows accepts
+pub(crate) const MIN_IPV4_MTU: u32 = 576;
+
+/// The smallest MTU IPv6 allows (RFC 8200)
+pub(crate) const MIN_IPV6_MTU: u32 = 1280;
+
+pub(crate) fn get_if_row2(luid: &NET_LUID_LH) -> Result<MIB_IF_ROW2, Error> {
+    let mut row: MIB_IF_ROW2 = unsafe { std::mem::zeroed() };
+    row.InterfaceLuid = *luid;
+    match unsafe { GetIfEntry2(&mut row) } {
+        NO_ERROR => Ok(row),
+        e => Err(Error::from_win32(e, "GetIfEntry2")),
+    }
+}
+
+pub(crate) fn get_ip_interface_entry(luid: &NET_LUID_LH, is_ipv6: bool) -> Result<MIB_IPINTERFACE_ROW, Error> {
+    let mut row: MIB_IPINTERFACE_ROW = unsafe { std::mem::zeroed() };
+    unsafe { InitializeIpInterfaceEntry(&mut row) };
+    row.Family = if is_ipv6 { AF_INET6 } else { AF_INET };
+    row.InterfaceLuid = *luid;
+    match unsafe { GetIpInterfaceEntry(&mut row) } {
+        NO_ERROR => Ok(row),
+        e => Err(Error::from_win32(e, "GetIpInterfaceEntry")),
+    }
+}
+
+/// Writes back a row obtained from [`get_ip_interface_entry`], `change` describes the modification for auditing
+pub(crate) fn set_ip_interface_entry(row: &mut MIB_IPINTERFACE_ROW, change: &str) -> Result<(), Error> {
+    let index_s = format!("index={}", row.InterfaceIndex);
+    let family_s = format!("family={}", if row.Family == AF_INET6 { "ipv6" } else { "ipv4" });
+    notify_command_observer("SetIpInterfaceEntry", &[&index_s, &family_s, change]);
+    if row.Family == AF_INET {
+        // SitePrefixLength must be zero for IPv4 rows, otherwise SetIpInterfaceEntry fails
+        row.SitePrefixLength = 0;
+    }
+    match unsafe { SetIpInterfaceEntry(row) } {
+        NO_ERROR => Ok(()),
+        e => Err(Error::from_win32(e, "SetIpInterfaceEntry")),
+    }
+}
+
+/// Assigns the unicast address `address/prefix_length` to the interface `luid`
+pub(crate) fn add_unicast_address(luid: &NET_LUID_LH, address: IpAddr, prefix_length: u8) -> Result<(), Error> {
+    add_unicast_address_ex(luid, address, prefix_length, false)
+}
+
+/// Like [`add_unicast_address`], with `skip_as_source` keeping Windows from picking the address as
+/// the source of outbound traffic
+pub(crate) fn add_unicast_address_ex(
+    luid: &NET_LUID_LH,
+    address: IpAddr,
+    prefix_length: u8,
+    skip_as_source: bool,
+) -> Result<(), Error> {
+    let mut row: MIB_UNICASTIPADDRESS_ROW = unsafe { std::mem::zeroed() };
+    unsafe { InitializeUnicastIpAddressEntry(&mut row) };
+    row.InterfaceLuid = *luid;
+    row.Address = ip_addr_to_sockaddr_inet(&address);
+    row.OnLinkPrefixLength = prefix_length;
+    row.SkipAsSource = skip_as_source as _;
+
+    let address_s = format!("address={}/{}", address, prefix_length);
+    let skip_as_source_s = format!("skip_as_source={}", skip_as_source);
+    notify_command_observer("CreateUnicastIpAddressEntry", &[&address_s, &skip_as_source_s]);
+
+    match unsafe { CreateUnicastIpAddressEntry(&row) } {
+        NO_ERROR => Ok(()),
+        e => Err(Error::from_win32(e, "CreateUnicastIpAddressEntry")),
+    }
+}
+
+/// Sets the `SkipAsSource` flag of an address already assigned to the interface `luid`
+pub(crate) fn set_unicast_address_skip_as_source(
+    luid: &NET_LUID_LH,
+    address: IpAddr,
+    skip_as_source: bool,
+) -> Result<(), Error> {
+    let mut row: MIB_UNICASTIPADDRESS_ROW = unsafe { std::mem::zeroed() };
+    row.InterfaceLuid = *luid;
+    row.Address = ip_addr_to_sockaddr_inet(&address);
+    match unsafe { GetUnicastIpAddressEntry(&mut row) } {
+        NO_ERROR => {}
+        e => return Err(Error::from_win32(e, "GetUnicastIpAddressEntry")),
+    }
+    row.SkipAsSource = skip_as_source as _;
+
+    let address_s = format!("address={}", address);
+    let skip_as_source_s = format!("skip_as_source={}", skip_as_source);
+    notify_command_observer("SetUnicastIpAddressEntry", &[&address_s, &skip_as_source_s]);
+
+    match unsafe { SetUnicastIpAddressEntry(&row) } {
+        NO_ERROR => Ok(()),
+        e => Err(Error::from_win32(e, "SetUnicastIpAddressEntry")),
+    }
+}
+
+/// Decodes the output of a console program such as `netsh`, which is written in the OEM code page
+/// rather than UTF-8, so non-ASCII adapter names in error messages are not garbled
+pub(crate) fn decode_oem_output(bytes: &[u8]) -> String {
+    use windows_sys::Win32::Globalization::{MultiByteToWideChar, CP_OEMCP};
+    if bytes.is_empty() {
+        return String::new();
+    }
+    let Ok(len) = i32::try_from(bytes.len()) else {
+        return String::from_utf8_lossy(bytes).into_owned();
+    };
+    let mut wide = vec![0u16; bytes.len()];
+    let written = unsafe { MultiByteToWideChar(CP_OEMCP, 0, bytes.as_ptr(), len, wide.as_mut_ptr(), len) };
+    if written <= 0 {
+        return String::from_utf8_lossy(bytes).into_owned();
+    }
+    String::from_utf16_lossy(&wide[..written as usize])
+}
+
 pub fn decode_utf16(string: &[u16]) -> String {
     let end = string.iter().position(|b| *b == 0).unwrap_or(string.len());
     String::from_utf16_lossy(&string[..end])
@@ -603,3 +1108,47 @@ struct _NET_LUID_LH_INFO {
     #[bitfield(name = "IfType", ty = "u64", bits = "48..=63")]
     _Value: [u8; 8],
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uuid_v5_matches_rfc4122() {
+        // The DNS namespace, 6ba7b810-9dad-11d1-80b4-00c04fd430c8
+        let namespace = 0x6ba7b810_9dad_11d1_80b4_00c04fd430c8;
+        let uuid = uuid_v5(namespace, b"python.org");
+        assert_eq!(uuid, 0x886313e1_3b8a_5372_9b90_0c9aee199e5d);
+    }
+
+    #[test]
+    fn dll_machine_is_read_from_pe_header() {
+        let mut image = vec![0u8; 0x86];
+        image[..2].copy_from_slice(b"MZ");
+        image[0x3c..0x40].copy_from_slice(&0x80u32.to_le_bytes());
+        image[0x80..0x84].copy_from_slice(b"PE\0\0");
+        image[0x84..0x86].copy_from_slice(&0x8664u16.to_le_bytes());
+        assert_eq!(dll_machine(&image), Some(0x8664));
+        assert_eq!(dll_machine(&image[..0x84]), None);
+        assert_eq!(dll_machine(b"not a dll"), None);
+    }
+
+    #[test]
+    fn ipv4_netmask_from_prefix() {
+        assert_eq!(ipv4_netmask_for_prefix(0).unwrap(), Ipv4Addr::new(0, 0, 0, 0));
+        assert_eq!(ipv4_netmask_for_prefix(24).unwrap(), Ipv4Addr::new(255, 255, 255, 0));
+        assert_eq!(ipv4_netmask_for_prefix(32).unwrap(), Ipv4Addr::new(255, 255, 255, 255));
+        assert!(ipv4_netmask_for_prefix(33).is_err());
+    }
+
+    #[test]
+    fn dns_servers_are_split_by_family() {
+        let dns: Vec<IpAddr> = ["8.8.8.8", "2001:4860:4860::8888", "1.1.1.1", "2606:4700:4700::1111"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        let (v4, v6) = split_dns_servers_by_family(&dns);
+        assert_eq!(v4, [dns[0], dns[2]]);
+        assert_eq!(v6, [dns[1], dns[3]]);
+    }
+}